@@ -0,0 +1,358 @@
+//! AST borrowing its selectors and arguments from the parsed input.
+//!
+//! Values are only allocated when the parser has to rewrite them, e.g. for escape sequences
+//! or percent-encoding, `into_owned` converts into the types of the crate root.
+
+use crate::ast::expr::{RED_ZONE, STACK_SIZE};
+use crate::Comparison;
+use serde::{Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+
+/// Serialized the same way as `crate::Value`
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "@type", content = "@data")]
+pub enum Value<'a> {
+    Unquoted(Cow<'a, str>),
+    String(Cow<'a, str>),
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+}
+
+/// Floats are compared by their bits, like `crate::Value`
+impl PartialEq for Value<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+            (Value::Unquoted(a), Value::Unquoted(b)) | (Value::String(a), Value::String(b)) => {
+                a == b
+            }
+            (Value::Null, Value::Null) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value<'_> {}
+
+impl<'a> Value<'a> {
+    /// Recognizes the typed value of an `Unquoted` value, see `crate::Value::recognize`
+    pub fn recognize(self) -> Self {
+        match &self {
+            Value::Unquoted(text) => match crate::Value::recognize(text) {
+                Some(crate::Value::Null) => Value::Null,
+                Some(crate::Value::Bool(value)) => Value::Bool(value),
+                Some(crate::Value::Integer(value)) => Value::Integer(value),
+                Some(crate::Value::Float(value)) => Value::Float(value),
+                _ => self,
+            },
+            _ => self,
+        }
+    }
+
+    /// The text of an `Unquoted` or `String` value
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Unquoted(text) | Value::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn into_owned(self) -> crate::Value {
+        match self {
+            Value::Unquoted(text) => crate::Value::Unquoted(text.into_owned()),
+            Value::String(text) => crate::Value::String(text.into_owned()),
+            Value::Null => crate::Value::Null,
+            Value::Bool(value) => crate::Value::Bool(value),
+            Value::Integer(value) => crate::Value::Integer(value),
+            Value::Float(value) => crate::Value::Float(value),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Clone)]
+pub struct Arguments<'a>(pub Vec<Value<'a>>);
+
+impl Arguments<'_> {
+    pub fn into_owned(self) -> crate::Arguments {
+        crate::Arguments(self.0.into_iter().map(Value::into_owned).collect())
+    }
+}
+
+/// The comparison is shared with the registry of the parser, so that parsing does not copy it
+#[derive(Debug, PartialEq, Eq, Serialize, Clone)]
+pub struct Constraint<'a> {
+    pub selector: Cow<'a, str>,
+    pub comparison: Arc<Comparison>,
+    pub arguments: Arguments<'a>,
+}
+
+impl Constraint<'_> {
+    pub fn into_owned(self) -> crate::Constraint {
+        crate::Constraint {
+            selector: self.selector.into_owned(),
+            comparison: Arc::try_unwrap(self.comparison).unwrap_or_else(|shared| (*shared).clone()),
+            arguments: self.arguments.into_owned(),
+        }
+    }
+}
+
+/// Serialized the same way as `crate::Expr`, and like it without recursing once per nesting
+/// level
+#[derive(Eq)]
+pub enum Expr<'a> {
+    Item(Constraint<'a>),
+    Exists(Cow<'a, str>),
+    And(Vec<Expr<'a>>),
+    Or(Vec<Expr<'a>>),
+    Not(Box<Expr<'a>>),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "@type", content = "@data")]
+enum ExprRef<'e, 'a> {
+    Item(&'e Constraint<'a>),
+    Exists(&'e str),
+    And(&'e [Expr<'a>]),
+    Or(&'e [Expr<'a>]),
+    Not(&'e Expr<'a>),
+}
+
+impl<'e, 'a> From<&'e Expr<'a>> for ExprRef<'e, 'a> {
+    fn from(expr: &'e Expr<'a>) -> Self {
+        match expr {
+            Expr::Item(constraint) => ExprRef::Item(constraint),
+            Expr::Exists(selector) => ExprRef::Exists(selector),
+            Expr::And(children) => ExprRef::And(children),
+            Expr::Or(children) => ExprRef::Or(children),
+            Expr::Not(child) => ExprRef::Not(child),
+        }
+    }
+}
+
+impl Serialize for Expr<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let repr = ExprRef::from(self);
+        stacker::maybe_grow(RED_ZONE, STACK_SIZE, || repr.serialize(serializer))
+    }
+}
+
+impl fmt::Debug for Expr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let repr = ExprRef::from(self);
+        stacker::maybe_grow(RED_ZONE, STACK_SIZE, || repr.fmt(f))
+    }
+}
+
+impl Clone for Expr<'_> {
+    fn clone(&self) -> Self {
+        enum Step<'e, 'a> {
+            Clone(&'e Expr<'a>),
+            And(usize),
+            Or(usize),
+            Not,
+        }
+
+        let mut todo = vec![Step::Clone(self)];
+        let mut done = vec![];
+        while let Some(step) = todo.pop() {
+            match step {
+                Step::Clone(expr) => match expr {
+                    Expr::Item(constraint) => done.push(Expr::Item(constraint.clone())),
+                    Expr::Exists(selector) => done.push(Expr::Exists(selector.clone())),
+                    Expr::And(children) | Expr::Or(children) => {
+                        todo.push(match expr {
+                            Expr::And(_) => Step::And(children.len()),
+                            _ => Step::Or(children.len()),
+                        });
+                        todo.extend(children.iter().rev().map(Step::Clone));
+                    }
+                    Expr::Not(child) => {
+                        todo.push(Step::Not);
+                        todo.push(Step::Clone(child));
+                    }
+                },
+                Step::And(len) => {
+                    let children = done.split_off(done.len() - len);
+                    done.push(Expr::And(children));
+                }
+                Step::Or(len) => {
+                    let children = done.split_off(done.len() - len);
+                    done.push(Expr::Or(children));
+                }
+                Step::Not => {
+                    let child = done.pop().expect("a negation has a cloned child");
+                    done.push(Expr::Not(Box::new(child)));
+                }
+            }
+        }
+        done.pop().expect("the root is cloned last")
+    }
+}
+
+impl PartialEq for Expr<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        let mut stack = vec![(self, other)];
+        while let Some(pair) = stack.pop() {
+            match pair {
+                (Expr::Item(a), Expr::Item(b)) if a == b => {}
+                (Expr::Exists(a), Expr::Exists(b)) if a == b => {}
+                (Expr::And(a), Expr::And(b)) | (Expr::Or(a), Expr::Or(b)) if a.len() == b.len() => {
+                    stack.extend(a.iter().zip(b))
+                }
+                (Expr::Not(a), Expr::Not(b)) => stack.push((a, b)),
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+impl Drop for Expr<'_> {
+    fn drop(&mut self) {
+        let mut stack = self.take_children();
+        while let Some(mut expr) = stack.pop() {
+            stack.append(&mut expr.take_children());
+        }
+    }
+}
+
+impl<'a> Expr<'a> {
+    pub fn into_owned(self) -> crate::Expr {
+        enum Step<'a> {
+            Convert(Expr<'a>),
+            And(usize),
+            Or(usize),
+            Not,
+        }
+
+        // Converted children are collected on `done` and joined once all of them are there
+        let mut todo = vec![Step::Convert(self)];
+        let mut done = vec![];
+        while let Some(step) = todo.pop() {
+            match step {
+                Step::Convert(mut expr) => match &mut expr {
+                    Expr::Item(constraint) => {
+                        let constraint = Constraint {
+                            selector: mem::take(&mut constraint.selector),
+                            comparison: Arc::clone(&constraint.comparison),
+                            arguments: Arguments(mem::take(&mut constraint.arguments.0)),
+                        };
+                        done.push(crate::Expr::Item(constraint.into_owned()));
+                    }
+                    Expr::Exists(selector) => {
+                        done.push(crate::Expr::Exists(mem::take(selector).into_owned()))
+                    }
+                    Expr::And(children) | Expr::Or(children) => {
+                        let children = mem::take(children);
+                        todo.push(match expr {
+                            Expr::And(_) => Step::And(children.len()),
+                            _ => Step::Or(children.len()),
+                        });
+                        todo.extend(children.into_iter().rev().map(Step::Convert));
+                    }
+                    Expr::Not(child) => {
+                        todo.push(Step::Not);
+                        todo.push(Step::Convert(mem::replace(&mut **child, Expr::And(vec![]))));
+                    }
+                },
+                Step::And(len) => {
+                    let children = done.split_off(done.len() - len);
+                    done.push(crate::Expr::And(children));
+                }
+                Step::Or(len) => {
+                    let children = done.split_off(done.len() - len);
+                    done.push(crate::Expr::Or(children));
+                }
+                Step::Not => {
+                    let child = done.pop().expect("a negation has a converted child");
+                    done.push(crate::Expr::Not(Box::new(child)));
+                }
+            }
+        }
+        done.pop().expect("the root is converted last")
+    }
+
+    /// Wraps this expression in `times` negations
+    pub(crate) fn negated(self, times: usize) -> Expr<'a> {
+        (0..times).fold(self, |expr, _| Expr::Not(Box::new(expr)))
+    }
+
+    /// Same as `crate::Expr::into_constraint`
+    pub fn into_constraint(mut self) -> Option<Constraint<'a>> {
+        match &mut self {
+            Expr::Item(constraint) => Some(Constraint {
+                selector: mem::take(&mut constraint.selector),
+                comparison: Arc::clone(&constraint.comparison),
+                arguments: Arguments(mem::take(&mut constraint.arguments.0)),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Same as `crate::Expr::into_children`
+    pub fn into_children(mut self) -> Vec<Expr<'a>> {
+        self.take_children()
+    }
+
+    fn take_children(&mut self) -> Vec<Expr<'a>> {
+        match self {
+            Expr::Item(_) | Expr::Exists(_) => vec![],
+            Expr::And(children) | Expr::Or(children) => mem::take(children),
+            Expr::Not(child) => vec![mem::replace(&mut **child, Expr::And(vec![]))],
+        }
+    }
+
+    /// Same as `crate::Expr::join`
+    pub(crate) fn join<I: IntoIterator<Item = Expr<'a>>>(
+        op: crate::Operator, exprs: I,
+    ) -> Option<Expr<'a>> {
+        let mut exprs = exprs.into_iter().collect::<Vec<_>>();
+        match exprs.len() {
+            0 => None,
+            1 => exprs.pop(),
+            _ => Some(match op {
+                crate::Operator::And => Expr::And(exprs),
+                crate::Operator::Or => Expr::Or(exprs),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::borrowed::{Arguments, Constraint, Expr, Value};
+    use crate::Comparison;
+    use std::borrow::Cow;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_into_owned() {
+        let code = "name==foo";
+        let borrowed = Expr::Not(Box::new(Expr::Item(Constraint {
+            selector: Cow::Borrowed(&code[..4]),
+            comparison: Arc::new(Comparison::EQUAL()),
+            arguments: Arguments(vec![
+                Value::Unquoted(Cow::Borrowed(&code[6..])),
+                Value::String(Cow::Owned("b r".to_string())),
+                Value::Float(1.5),
+            ]),
+        })));
+        let owned = crate::Expr::Not(Box::new(crate::Expr::Item(crate::Constraint {
+            selector: "name".to_string(),
+            comparison: Comparison::EQUAL(),
+            arguments: crate::Arguments(vec![
+                crate::Value::Unquoted("foo".to_string()),
+                crate::Value::String("b r".to_string()),
+                crate::Value::Float(1.5),
+            ]),
+        })));
+        assert_eq!(borrowed.into_owned(), owned);
+    }
+}
@@ -1,42 +1,183 @@
 use crate::error::ParserError;
-use crate::ParserResult;
-use regex::RegexSet;
+use crate::{ComparisonStyle, Format, Formatter, ParserResult};
 use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 
 macro_rules! default_comparisons {
-    ($name:ident, $multi:expr, $($symbol:expr),+) => {
+    ($name:ident, $id:expr, $category:expr, $description:expr, $arity:expr, $($symbol:expr),+) => {
         #[allow(non_snake_case)]
         pub fn $name() -> Comparison {
-            Comparison::new(&[$($symbol),+], $multi).unwrap()
+            Comparison::new(&[$($symbol),+], $arity)
+                .unwrap()
+                .id($id)
+                .category($category)
+                .description($description)
         }
     };
-    ( $($name:ident, $multi:expr, [$($symbol:expr),+];)+ ) => {
+    ( $($name:ident, $id:expr, $category:expr, $description:expr, $arity:expr, [$($symbol:expr),+];)+ ) => {
         impl Comparison {
             $(
-                default_comparisons!($name, $multi, $($symbol),+);
+                default_comparisons!($name, $id, $category, $description, $arity, $($symbol),+);
             )+
         }
+
+        /// The built-in comparisons, built once and shared by the parsed constraints
+        pub(crate) mod shared {
+            use crate::Comparison;
+            use std::sync::Arc;
+
+            lazy_static! {
+                $(pub(crate) static ref $name: Arc<Comparison> = Arc::new(Comparison::$name());)+
+            }
+        }
     }
 }
 
-lazy_static! {
-    static ref COMP_OP_RE: RegexSet = RegexSet::new(&[r"^=[a-zA-Z]*=$", r"^[<>]=?$"]).unwrap();
+/// Number of arguments a comparison takes
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+pub enum Arity {
+    Exact(usize),
+    /// From `min` to `max` arguments, both inclusive
+    Range {
+        min: usize,
+        max: usize,
+    },
+    AtLeast(usize),
+    Any,
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arity::Exact(count) => write!(f, "exactly {}", count),
+            Arity::Range { min, max } => write!(f, "{} to {}", min, max),
+            Arity::AtLeast(min) => write!(f, "at least {}", min),
+            Arity::Any => write!(f, "any number"),
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exact(expected) => count == expected,
+            Arity::Range { min, max } => min <= count && count <= max,
+            Arity::AtLeast(min) => count >= min,
+            Arity::Any => true,
+        }
+    }
+
+    /// Whether more than one argument is accepted, the arguments are then written as a list
+    pub fn is_multi(&self) -> bool {
+        match *self {
+            Arity::Exact(count) => count > 1,
+            Arity::Range { max, .. } => max > 1,
+            Arity::AtLeast(_) | Arity::Any => true,
+        }
+    }
+
+    pub(crate) fn check(&self, count: usize) -> ParserResult<()> {
+        if self.accepts(count) {
+            Ok(())
+        } else {
+            Err(ParserError::InvalidConstraintArgs { expected: *self, count })
+        }
+    }
+}
+
+/// A comparison operator. Comparisons are equal if their ids are, whatever their symbols
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "ComparisonRepr")]
 pub struct Comparison {
+    pub(crate) id: String,
     pub(crate) symbols: Vec<String>,
-    pub(crate) multi_values: bool,
+    pub(crate) arity: Arity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) category: Option<String>,
+}
+
+/// Comparisons serialized without an id take their first symbol, the ones serialized with
+/// `multi_values` instead of an arity take at least one argument if it is set, else one
+#[derive(Deserialize)]
+struct ComparisonRepr {
+    #[serde(default)]
+    id: Option<String>,
+    symbols: Vec<String>,
+    #[serde(default)]
+    arity: Option<Arity>,
+    #[serde(default)]
+    multi_values: Option<bool>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    category: Option<String>,
+}
+
+impl TryFrom<ComparisonRepr> for Comparison {
+    type Error = ParserError;
+
+    fn try_from(repr: ComparisonRepr) -> ParserResult<Self> {
+        let arity = match (repr.arity, repr.multi_values) {
+            (Some(arity), _) => arity,
+            (None, Some(true)) => Arity::AtLeast(1),
+            (None, Some(false)) => Arity::Exact(1),
+            (None, None) => {
+                return Err(ParserError::LackOfField {
+                    ty: "Comparison".to_string(),
+                    field: "arity".to_string(),
+                })
+            }
+        };
+        let symbols = repr.symbols.iter().map(String::as_str).collect::<Vec<_>>();
+        let comparison = Comparison::new(&symbols, arity)?;
+        Ok(Comparison {
+            id: repr.id.unwrap_or(comparison.id),
+            description: repr.description,
+            category: repr.category,
+            ..comparison
+        })
+    }
+}
+
+impl PartialEq for Comparison {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Comparison {}
+
+impl Hash for Comparison {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state)
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_to(f, &Formatter::default())
+    }
 }
 
-impl ToString for Comparison {
-    fn to_string(&self) -> String {
-        self.symbols.first().unwrap().clone()
+/// FIQL has no symbolic comparisons, `ComparisonStyle::Symbolic` writes the first symbol there
+impl Format for Comparison {
+    fn write_to(&self, f: &mut dyn fmt::Write, formatter: &Formatter) -> fmt::Result {
+        let style = match formatter.comparison_style {
+            ComparisonStyle::Symbolic if formatter.is_fiql() => ComparisonStyle::First,
+            style => style,
+        };
+        f.write_str(self.symbol(style))
     }
 }
 
 impl Comparison {
-    pub fn new(symbols: &[&str], multi_values: bool) -> ParserResult<Comparison> {
+    /// Which symbols a parser accepts is checked when registering the comparison, see
+    /// `ParserOptions::symbol_patterns`
+    pub fn new(symbols: &[&str], arity: Arity) -> ParserResult<Comparison> {
         let symbols = symbols
             .iter()
             .map(|&sym| Self::is_valid_symbol(sym))
@@ -44,35 +185,97 @@ impl Comparison {
         if symbols.is_empty() {
             return Err(ParserError::EmptySymbol());
         }
-        Ok(Comparison { symbols, multi_values })
+        Ok(Comparison { id: symbols[0].clone(), symbols, arity, description: None, category: None })
+    }
+
+    /// The stable identifier of the comparison, the first symbol unless set. The built-in
+    /// comparisons are `equal`, `not_equal`, `greater_than`, `greater_than_or_equal`,
+    /// `less_than`, `less_than_or_equal`, `in` and `out`
+    pub fn id(mut self, id: &str) -> Self {
+        self.id = id.to_string();
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// A free-form group of comparisons, e.g. `equality`, `ordering` or `membership` for the
+    /// built-in ones
+    pub fn category(mut self, category: &str) -> Self {
+        self.category = Some(category.to_string());
+        self
+    }
+
+    /// Adds `symbol` to the symbols, keeping the id
+    pub fn alias(mut self, symbol: &str) -> ParserResult<Self> {
+        let symbol = Self::is_valid_symbol(symbol)?;
+        if !self.symbols.contains(&symbol) {
+            self.symbols.push(symbol);
+        }
+        Ok(self)
     }
 
     fn is_valid_symbol(symbol: &str) -> ParserResult<String> {
-        if COMP_OP_RE.is_match(symbol) || symbol == "!=" {
+        let reserved = |c: char| c.is_whitespace() || "\"'();,".contains(c);
+        if !symbol.is_empty() && !symbol.contains(reserved) {
             Ok(symbol.to_string())
         } else {
             Err(ParserError::InvalidComparison(symbol.to_string()))
         }
     }
 
+    pub fn get_id(&self) -> &str {
+        &self.id
+    }
+
     pub fn get_symbols(&self) -> &[String] {
         &self.symbols
     }
 
+    /// The symbol written in `style`, the first one if none fits it
+    pub fn symbol(&self, style: ComparisonStyle) -> &str {
+        let fits = |symbol: &&String| match style {
+            ComparisonStyle::First => true,
+            ComparisonStyle::Fiql => {
+                symbol.as_str() == "!=" || (symbol.starts_with('=') && symbol.ends_with('='))
+            }
+            ComparisonStyle::Symbolic => !symbol.chars().any(|c| c.is_ascii_alphanumeric()),
+        };
+        // `new` and deserializing reject comparisons without symbols
+        self.symbols.iter().find(fits).unwrap_or(&self.symbols[0])
+    }
+
+    pub fn get_description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn get_category(&self) -> Option<&str> {
+        self.category.as_deref()
+    }
+
     pub fn is_multi(&self) -> bool {
-        self.multi_values
+        self.arity.is_multi()
+    }
+
+    pub fn arity(&self) -> Arity {
+        self.arity
     }
 }
 
 default_comparisons! {
-    EQUAL, false, ["=="];
-    NOT_EQUAL, false, ["!="];
-    GREATER_THAN, false, ["=gt=", ">"];
-    GREATER_THAN_OR_EQUAL, false, ["=ge=", ">="];
-    LESS_THAN, false, ["=lt=", "<"];
-    LESS_THAN_OR_EQUAL, false, ["=le=", "<="];
-    IN, true, ["=in="];
-    OUT, true, ["=out="];
+    EQUAL, "equal", "equality", "Equal to the argument", Arity::Exact(1), ["=="];
+    NOT_EQUAL, "not_equal", "equality", "Not equal to the argument", Arity::Exact(1), ["!="];
+    GREATER_THAN, "greater_than", "ordering", "Greater than the argument", Arity::Exact(1),
+        ["=gt=", ">"];
+    GREATER_THAN_OR_EQUAL, "greater_than_or_equal", "ordering",
+        "Greater than or equal to the argument", Arity::Exact(1), ["=ge=", ">="];
+    LESS_THAN, "less_than", "ordering", "Less than the argument", Arity::Exact(1), ["=lt=", "<"];
+    LESS_THAN_OR_EQUAL, "less_than_or_equal", "ordering", "Less than or equal to the argument",
+        Arity::Exact(1), ["=le=", "<="];
+    IN, "in", "membership", "Equal to one of the arguments", Arity::AtLeast(1), ["=in="];
+    OUT, "out", "membership", "Equal to none of the arguments", Arity::AtLeast(1), ["=out="];
 }
 
 #[cfg(test)]
@@ -82,16 +285,48 @@ mod tests {
     #[test]
     fn test_valid() -> anyhow::Result<()> {
         let symbols = vec!["==", "=eq="];
-        Comparison::new(&symbols, false)?;
+        Comparison::new(&symbols, Arity::Exact(1))?;
+        Comparison::new(&["=~", "!~", "=in2="], Arity::Exact(1))?;
 
         Ok(())
     }
 
     #[test]
     fn test_invalid() -> anyhow::Result<()> {
-        assert!(Comparison::new(&["~="], false).is_err());
-        assert!(Comparison::new(&["><="], false).is_err());
-        assert!(Comparison::new(&["test="], false).is_err());
+        assert!(Comparison::new(&[""], Arity::Exact(1)).is_err());
+        assert!(Comparison::new(&["= ="], Arity::Exact(1)).is_err());
+        assert!(Comparison::new(&["=(="], Arity::Exact(1)).is_err());
+        assert!(Comparison::new(&[], Arity::Exact(1)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_arity() {
+        assert!(Arity::Exact(2).accepts(2));
+        assert!(!Arity::Exact(2).accepts(1));
+        assert!(Arity::Range { min: 0, max: 1 }.accepts(0));
+        assert!(!Arity::Range { min: 0, max: 1 }.accepts(2));
+        assert!(!Arity::Range { min: 0, max: 1 }.is_multi());
+        assert!(Arity::AtLeast(1).accepts(5));
+        assert!(!Arity::AtLeast(1).accepts(0));
+        assert!(Arity::Any.accepts(0));
+        assert_eq!(Comparison::IN().arity(), Arity::AtLeast(1));
+        assert_eq!(Comparison::EQUAL().arity(), Arity::Exact(1));
+    }
+
+    #[test]
+    fn test_id() -> anyhow::Result<()> {
+        let after = Comparison::GREATER_THAN().alias("=after=")?;
+        assert_eq!(after, Comparison::GREATER_THAN());
+        assert_eq!(after.get_id(), "greater_than");
+        assert_eq!(after.get_symbols(), ["=gt=", ">", "=after="]);
+        assert_eq!(after.get_category(), Some("ordering"));
+        assert_eq!(after.to_string(), "=gt=");
+
+        let gt = Comparison::new(&["=gt="], Arity::Exact(1))?;
+        assert_eq!(gt.get_id(), "=gt=");
+        assert_ne!(gt, Comparison::GREATER_THAN());
+        assert_eq!(gt.id("greater_than"), Comparison::GREATER_THAN());
         Ok(())
     }
 }
@@ -1,49 +1,96 @@
-use crate::error::ParserError;
-use crate::Comparison;
+use crate::ast::value::is_bare;
 use crate::ParserResult;
-use itertools::Itertools;
+use crate::Value;
+use crate::{Arity, Comparison};
+use crate::{Format, Formatter};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
-pub struct Arguments(pub Vec<String>);
-
-static RESERVED_CHARS: &[char] = &['"', '\'', '(', ')', ';', ',', '=', '!', '~', '<', '>', ' '];
-
-fn add_quote(arg: &str) -> String {
-    if arg.find(|c| RESERVED_CHARS.contains(&c)).is_some() {
-        let mut escaped = false;
-        let mut should_double = false;
-
-        for c in arg.chars() {
-            if c == '\\' {
-                escaped = true;
-            } else if c == '\'' && !escaped {
-                should_double = true;
-                break;
-            } else if c == '"' && !escaped {
-                break;
-            } else {
-                escaped = false;
-            }
-        }
+pub struct Arguments(pub Vec<Value>);
 
-        if should_double {
-            format!(r#""{}""#, arg)
+fn percent_encode(value: &str, allowed: fn(char) -> bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for c in value.chars() {
+        if allowed(c) {
+            encoded.push(c);
         } else {
-            format!("'{}'", arg)
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
         }
+    }
+    encoded
+}
+
+fn is_fiql_unreserved(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-._~:".contains(c)
+}
+
+/// Percent-encodes the characters FIQL does not allow in a selector
+pub(crate) fn fiql_selector(selector: &str) -> String {
+    percent_encode(selector, is_fiql_unreserved)
+}
+
+/// Writes `selector`, percent-encoded in FIQL. Fails for selectors the dialect cannot read
+/// back, an empty one or in RSQL one with reserved chars or whitespace, unless the formatter is
+/// lossy
+pub(crate) fn write_selector(
+    selector: &str, f: &mut dyn fmt::Write, formatter: &Formatter,
+) -> fmt::Result {
+    if !formatter.lossy && (selector.is_empty() || !formatter.is_fiql() && !is_bare(selector)) {
+        return Err(fmt::Error);
+    }
+    if formatter.is_fiql() {
+        f.write_str(&fiql_selector(selector))
     } else {
-        arg.to_string()
+        f.write_str(selector)
     }
 }
 
-impl ToString for Arguments {
-    fn to_string(&self) -> String {
-        if self.0.len() > 1 {
-            format!("({})", self.0.iter().map(|s| add_quote(s.as_str())).join(","))
-        } else {
-            add_quote(&self.0.first().cloned().unwrap_or_default())
+fn is_fiql_arg_char(c: char) -> bool {
+    is_fiql_unreserved(c) || "!$'*+=".contains(c)
+}
+
+/// Percent-encodes the characters FIQL does not allow in an argument
+pub(crate) fn fiql_argument(text: &str) -> String {
+    percent_encode(text, is_fiql_arg_char)
+}
+
+impl Arguments {
+    /// Serializes in FIQL, percent-encoding the characters FIQL does not allow. Fails if an
+    /// argument cannot be written, see `Value`
+    pub fn to_fiql_string(&self) -> Result<String, fmt::Error> {
+        Formatter::fiql().format(self)
+    }
+
+    /// Writes the arguments in parentheses if `list` or if there are none, e.g. `title=isnull=()`,
+    /// else the first one as it is
+    fn write(&self, f: &mut dyn fmt::Write, formatter: &Formatter, list: bool) -> fmt::Result {
+        if let (false, Some(arg)) = (list, self.0.first()) {
+            return arg.write_to(f, formatter);
+        }
+        f.write_char('(')?;
+        for (idx, arg) in self.0.iter().enumerate() {
+            if idx > 0 {
+                f.write_char(',')?;
+            }
+            arg.write_to(f, formatter)?;
         }
+        f.write_char(')')
+    }
+}
+
+impl fmt::Display for Arguments {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_to(f, &Formatter::default().lossy())
+    }
+}
+
+impl Format for Arguments {
+    fn write_to(&self, f: &mut dyn fmt::Write, formatter: &Formatter) -> fmt::Result {
+        self.write(f, formatter, self.0.len() > 1)
     }
 }
 
@@ -54,39 +101,68 @@ pub struct Constraint {
     pub arguments: Arguments,
 }
 
-impl ToString for Constraint {
-    fn to_string(&self) -> String {
-        format!("{}{}{}", self.selector, self.comparison.to_string(), self.arguments.to_string())
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_to(f, &Formatter::default().lossy())
+    }
+}
+
+impl Format for Constraint {
+    fn write_to(&self, f: &mut dyn fmt::Write, formatter: &Formatter) -> fmt::Result {
+        write_selector(&self.selector, f, formatter)?;
+        self.comparison.write_to(f, formatter)?;
+        self.arguments.write(f, formatter, self.is_list())
     }
 }
 
 impl Constraint {
-    pub fn new(selector: &str, comparison: Comparison, arguments: &[&str]) -> ParserResult<Self> {
-        if comparison.multi_values {
-            let expect_args = "> 1".to_string();
-            if arguments.len() <= 1 {
-                return Err(ParserError::InvalidConstraintArgs(expect_args, arguments.len()));
-            }
-        } else {
-            let expect_args = "== 1".to_string();
-            if arguments.len() != 1 {
-                return Err(ParserError::InvalidConstraintArgs(expect_args, arguments.len()));
-            }
-        }
+    /// Serializes in FIQL, percent-encoding the characters FIQL does not allow. Fails if an
+    /// argument cannot be written, see `Value`
+    pub fn to_fiql_string(&self) -> Result<String, fmt::Error> {
+        Formatter::fiql().format(self)
+    }
+
+    /// A constraint that allocates nothing, left behind when one is moved out of an `Expr`
+    pub(crate) fn vacant() -> Self {
+        let comparison = Comparison {
+            id: String::new(),
+            symbols: vec![],
+            arity: Arity::Any,
+            description: None,
+            category: None,
+        };
+        Constraint { selector: String::new(), comparison, arguments: Arguments(vec![]) }
+    }
+
+    /// Multi-value comparisons keep the list syntax even for a single argument
+    fn is_list(&self) -> bool {
+        self.comparison.is_multi() || self.arguments.0.len() > 1
+    }
 
+    /// `arguments` are `Value`s or anything that converts into one, `&str` into
+    /// `Value::Unquoted` if RSQL can write it without quotes. Fails for a non-finite `Float`
+    pub fn new<V: Into<Value> + Clone>(
+        selector: &str, comparison: Comparison, arguments: &[V],
+    ) -> ParserResult<Self> {
+        comparison.arity.check(arguments.len())?;
+        let arguments = arguments.iter().cloned().map(Into::into).collect::<Vec<Value>>();
+        arguments.iter().try_for_each(Value::check)?;
         Ok(Constraint {
             selector: selector.to_string(),
             comparison,
-            arguments: Arguments(arguments.iter().map(ToString::to_string).collect()),
+            arguments: Arguments(arguments),
         })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::ast::comparison::Comparison;
+    use crate::ast::comparison::{Arity, Comparison};
     use crate::ast::constraint::Constraint;
-    use crate::{Arguments, ParserResult};
+    use crate::error::ParserError;
+    use crate::{Arguments, Formatter, ParserResult, Value};
+    use std::convert::TryFrom;
+    use std::fmt;
 
     #[test]
     fn test_new() -> ParserResult<()> {
@@ -95,12 +171,25 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_arity() -> ParserResult<()> {
+        let constraint = Constraint::new("genres", Comparison::IN(), &["sci-fi"])?;
+        assert_eq!(constraint.to_string(), "genres=in=(sci-fi)");
+        assert_eq!(constraint.to_fiql_string().as_deref(), Ok("genres=in=(sci-fi)"));
+        assert!(matches!(
+            Constraint::new("genres", Comparison::IN(), &[] as &[&str]),
+            Err(ParserError::InvalidConstraintArgs { expected: Arity::AtLeast(1), count: 0 })
+        ));
+        assert!(Constraint::new("year", Comparison::EQUAL(), &["1", "2"]).is_err());
+        Ok(())
+    }
+
     #[test]
     fn arguments_to_string() {
         let args = Arguments(
             vec!["String", "Hello World!", r#""double quoted""#, "it's really", "only\"test"]
                 .into_iter()
-                .map(ToString::to_string)
+                .map(Value::from)
                 .collect(),
         );
         assert_eq!(
@@ -108,4 +197,52 @@ mod tests {
             r#"(String,'Hello World!','"double quoted"',"it's really",'only"test')"#
         )
     }
+
+    #[test]
+    fn arguments_to_string_escaped() {
+        let args = Arguments(
+            vec![r#"it's "quoted""#, r"back\ slash", r"a\b"].into_iter().map(Value::from).collect(),
+        );
+        assert_eq!(args.to_string(), r#"('it\'s "quoted"','back\\ slash',a\b)"#)
+    }
+
+    #[test]
+    fn test_typed_arguments() -> ParserResult<()> {
+        let values = [Value::String("2003".to_string()), Value::Integer(2003), Value::Null];
+        let constraint = Constraint::new("year", Comparison::IN(), &values)?;
+        assert_eq!(constraint.to_string(), "year=in=('2003',2003,null)");
+        assert_eq!(constraint.to_fiql_string().as_deref(), Ok("year=in=(2003,2003,null)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unwritable_selector() -> ParserResult<()> {
+        let constraint = Constraint::new("a b", Comparison::EQUAL(), &["1"])?;
+        assert_eq!(Formatter::rsql().format(&constraint), Err(fmt::Error));
+        assert_eq!(constraint.to_fiql_string().as_deref(), Ok("a%20b==1"));
+        assert_eq!(constraint.to_string(), "a b==1");
+
+        let constraint = Constraint::new("", Comparison::EQUAL(), &["1"])?;
+        assert_eq!(Formatter::rsql().format(&constraint), Err(fmt::Error));
+        assert_eq!(constraint.to_fiql_string(), Err(fmt::Error));
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_finite() {
+        for value in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(matches!(
+                Constraint::new("price", Comparison::EQUAL(), &[Value::Float(*value)]),
+                Err(ParserError::InvalidValue(_))
+            ));
+            assert!(Value::try_from(*value).is_err());
+        }
+        // Built without the checks, they cannot be written
+        let args = Arguments(vec![Value::Float(f64::NAN)]);
+        assert!(args.to_fiql_string().is_err());
+        assert_eq!(args.to_string(), "NaN");
+
+        let json = serde_json::to_string(&Value::Float(1.5)).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&json).ok(), Some(Value::Float(1.5)));
+    }
 }
@@ -1,52 +1,334 @@
+use crate::ast::constraint::{fiql_selector, write_selector};
+use crate::ast::format::write_lines;
 use crate::Comparison;
 use crate::Constraint;
 use crate::Operator;
 use crate::ParserResult;
-use serde::{Deserialize, Serialize};
+use crate::Value;
+use crate::{Format, Formatter};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 use std::fmt::Debug;
+use std::mem;
 
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
-#[serde(tag = "@type", content = "@data")]
+/// Stack left when serde recursion grows the stack, and the size of every new segment
+pub(crate) const RED_ZONE: usize = 64 * 1024;
+pub(crate) const STACK_SIZE: usize = 1024 * 1024;
+
+/// Serialized as `{"@type": "And", "@data": [...]}`.
+///
+/// `Clone`, `PartialEq`, `Debug`, serde and `Drop` do not recurse once per nesting level, so
+/// that they handle any expression the parsers accept
+#[derive(Eq)]
 pub enum Expr {
     Item(Constraint),
-    Node(Operator, Box<Expr>, Box<Expr>),
+    /// A bare selector, true if the selector is present or true. Only FIQL has a syntax for it,
+    /// formatting it in RSQL fails and `Display` writes it as in FIQL
+    Exists(String),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    /// Negation, written as a `!` or `not` prefix in RSQL. FIQL has no syntax for it, formatting
+    /// it in FIQL fails
+    Not(Box<Expr>),
 }
 
-impl ToString for Expr {
-    fn to_string(&self) -> String {
-        match &self {
-            Expr::Item(cons) => cons.to_string(),
-            Expr::Node(op, left, right) => {
-                let op_str = match op {
-                    Operator::And => ";",
-                    Operator::Or => ",",
-                };
-                match (left.as_ref(), right.as_ref()) {
-                    (Expr::Item(left), Expr::Item(right)) => {
-                        format!("{}{}{}", left.to_string(), op_str, right.to_string())
-                    }
-                    (left @ Expr::Node(_, _, _), Expr::Item(right)) => {
-                        format!("{}{}{}", left.to_string(), op_str, right.to_string())
-                    }
-                    (Expr::Item(left), right @ Expr::Node(_, _, _)) => {
-                        format!("{}{}({})", left.to_string(), op_str, right.to_string())
+/// Shadow of `Expr` for the derived serde and `Debug` code, which recurses once per nesting
+/// level and therefore runs in `stacker::maybe_grow`
+#[derive(Debug, Serialize)]
+#[serde(tag = "@type", content = "@data")]
+enum ExprRef<'e> {
+    Item(&'e Constraint),
+    Exists(&'e str),
+    And(&'e [Expr]),
+    Or(&'e [Expr]),
+    Not(&'e Expr),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "@type", content = "@data")]
+enum ExprRepr {
+    Item(Constraint),
+    Exists(String),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+}
+
+impl<'e> From<&'e Expr> for ExprRef<'e> {
+    fn from(expr: &'e Expr) -> Self {
+        match expr {
+            Expr::Item(constraint) => ExprRef::Item(constraint),
+            Expr::Exists(selector) => ExprRef::Exists(selector),
+            Expr::And(children) => ExprRef::And(children),
+            Expr::Or(children) => ExprRef::Or(children),
+            Expr::Not(child) => ExprRef::Not(child),
+        }
+    }
+}
+
+impl Serialize for Expr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let repr = ExprRef::from(self);
+        stacker::maybe_grow(RED_ZONE, STACK_SIZE, || repr.serialize(serializer))
+    }
+}
+
+impl Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let repr = ExprRef::from(self);
+        stacker::maybe_grow(RED_ZONE, STACK_SIZE, || repr.fmt(f))
+    }
+}
+
+impl Clone for Expr {
+    fn clone(&self) -> Self {
+        enum Step<'e> {
+            Clone(&'e Expr),
+            And(usize),
+            Or(usize),
+            Not,
+        }
+
+        // Cloned children are collected on `done` and joined once all of them are there
+        let mut todo = vec![Step::Clone(self)];
+        let mut done = vec![];
+        while let Some(step) = todo.pop() {
+            match step {
+                Step::Clone(expr) => match expr {
+                    Expr::Item(constraint) => done.push(Expr::Item(constraint.clone())),
+                    Expr::Exists(selector) => done.push(Expr::Exists(selector.clone())),
+                    Expr::And(children) | Expr::Or(children) => {
+                        todo.push(match expr {
+                            Expr::And(_) => Step::And(children.len()),
+                            _ => Step::Or(children.len()),
+                        });
+                        todo.extend(children.iter().rev().map(Step::Clone));
                     }
-                    (left, right) => {
-                        format!("{}{}({})", left.to_string(), op_str, right.to_string())
+                    Expr::Not(child) => {
+                        todo.push(Step::Not);
+                        todo.push(Step::Clone(child));
                     }
+                },
+                Step::And(len) => {
+                    let children = done.split_off(done.len() - len);
+                    done.push(Expr::And(children));
+                }
+                Step::Or(len) => {
+                    let children = done.split_off(done.len() - len);
+                    done.push(Expr::Or(children));
+                }
+                Step::Not => {
+                    let child = done.pop().expect("a negation has a cloned child");
+                    done.push(Expr::Not(Box::new(child)));
+                }
+            }
+        }
+        done.pop().expect("the root is cloned last")
+    }
+}
+
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        let mut stack = vec![(self, other)];
+        while let Some(pair) = stack.pop() {
+            match pair {
+                (Expr::Item(a), Expr::Item(b)) if a == b => {}
+                (Expr::Exists(a), Expr::Exists(b)) if a == b => {}
+                (Expr::And(a), Expr::And(b)) | (Expr::Or(a), Expr::Or(b)) if a.len() == b.len() => {
+                    stack.extend(a.iter().zip(b))
+                }
+                (Expr::Not(a), Expr::Not(b)) => stack.push((a, b)),
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+impl<'de> Deserialize<'de> for Expr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr =
+            stacker::maybe_grow(RED_ZONE, STACK_SIZE, || ExprRepr::deserialize(deserializer))?;
+        Ok(match repr {
+            ExprRepr::Item(constraint) => Expr::Item(constraint),
+            ExprRepr::Exists(selector) => Expr::Exists(selector),
+            ExprRepr::And(children) => Expr::And(children),
+            ExprRepr::Or(children) => Expr::Or(children),
+            ExprRepr::Not(child) => Expr::Not(child),
+        })
+    }
+}
+
+/// Moves the children onto a heap stack instead of dropping them recursively, every popped
+/// expression is childless by the time it is dropped
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut stack = self.take_children();
+        while let Some(mut expr) = stack.pop() {
+            stack.append(&mut expr.take_children());
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_to(f, &Formatter::default().lossy())
+    }
+}
+
+/// Writes only the parentheses that AND-over-OR precedence and nested nodes of the same operator
+/// need, so that the output parses back to the same `Expr`. Nodes with a single child are
+/// written as the child
+impl Format for Expr {
+    fn write_to(&self, f: &mut dyn fmt::Write, formatter: &Formatter) -> fmt::Result {
+        if let (Some(width), false) = (formatter.max_width, formatter.is_fiql()) {
+            return write_lines(self, f, formatter, width);
+        }
+        enum Step<'e> {
+            Expr(&'e Expr),
+            Str(&'static str),
+        }
+
+        // Explicit stack, so that deeply nested expressions cannot overflow the call stack
+        let mut stack = vec![Step::Expr(self)];
+        while let Some(step) = stack.pop() {
+            let expr = match step {
+                Step::Str(s) => {
+                    f.write_str(s)?;
+                    continue;
+                }
+                Step::Expr(expr) => expr,
+            };
+            let (op, children) = match expr {
+                Expr::Item(cons) => {
+                    cons.write_to(f, formatter)?;
+                    continue;
+                }
+                Expr::Exists(selector) if formatter.is_fiql() => {
+                    write_selector(selector, f, formatter)?;
+                    continue;
+                }
+                Expr::Exists(selector) if formatter.lossy => {
+                    f.write_str(&fiql_selector(selector))?;
+                    continue;
+                }
+                Expr::Exists(_) => return Err(fmt::Error),
+                Expr::And(children) => (formatter.and(), &children[..]),
+                Expr::Or(children) => (formatter.or(), &children[..]),
+                Expr::Not(_) if formatter.is_fiql() && !formatter.lossy => return Err(fmt::Error),
+                Expr::Not(child) => {
+                    f.write_str(formatter.not())?;
+                    (formatter.and(), std::slice::from_ref(&**child))
+                }
+            };
+            for (idx, child) in children.iter().enumerate().rev() {
+                let parens = needs_parens(expr, child);
+                if parens {
+                    stack.push(Step::Str(")"));
+                }
+                stack.push(Step::Expr(child));
+                if parens {
+                    stack.push(Step::Str("("));
+                }
+                if idx > 0 {
+                    stack.push(Step::Str(op));
                 }
             }
         }
+        Ok(())
     }
 }
 
+/// Whether `child` is written in parentheses as an operand of `parent`
+pub(crate) fn needs_parens(parent: &Expr, child: &Expr) -> bool {
+    // AND binds tighter than OR, so only AND nodes can go bare into an OR node
+    !matches!(
+        (parent, child),
+        (_, Expr::Item(_)) | (_, Expr::Exists(_)) | (_, Expr::Not(_)) | (Expr::Or(_), Expr::And(_))
+    )
+}
+
 impl Expr {
-    pub fn boxed_item(
-        selector: &str, comparison: Comparison, arguments: &[&str],
+    pub fn boxed_item<V: Into<Value> + Clone>(
+        selector: &str, comparison: Comparison, arguments: &[V],
     ) -> ParserResult<Box<Expr>> {
         let res = Constraint::new(selector, comparison, arguments)?;
         Ok(Box::new(Expr::Item(res)))
     }
+
+    pub fn item<V: Into<Value> + Clone>(
+        selector: &str, comparison: Comparison, arguments: &[V],
+    ) -> ParserResult<Expr> {
+        Ok(Expr::Item(Constraint::new(selector, comparison, arguments)?))
+    }
+
+    /// Serializes in FIQL, percent-encoding the characters FIQL does not allow. Fails if the
+    /// expression contains an `Expr::Not`, FIQL has no syntax for it
+    pub fn to_fiql_string(&self) -> Result<String, fmt::Error> {
+        Formatter::fiql().format(self)
+    }
+
+    /// The operator joining the children of this node, `None` for a leaf or a negation
+    pub fn operator(&self) -> Option<Operator> {
+        match self {
+            Expr::Item(_) | Expr::Exists(_) | Expr::Not(_) => None,
+            Expr::And(_) => Some(Operator::And),
+            Expr::Or(_) => Some(Operator::Or),
+        }
+    }
+
+    /// All constraints of this expression, from left to right
+    pub fn constraints(&self) -> Vec<&Constraint> {
+        let mut constraints = vec![];
+        let mut stack = vec![self];
+        while let Some(expr) = stack.pop() {
+            match expr {
+                Expr::Item(constraint) => constraints.push(constraint),
+                Expr::Exists(_) => {}
+                Expr::And(children) | Expr::Or(children) => stack.extend(children.iter().rev()),
+                Expr::Not(child) => stack.push(child),
+            }
+        }
+        constraints
+    }
+
+    /// The children of an `And` or `Or` node or the child of a `Not`, empty for a leaf. `Expr`
+    /// implements `Drop`, so a `match` on an `Expr` value cannot move them out
+    pub fn into_children(mut self) -> Vec<Expr> {
+        self.take_children()
+    }
+
+    /// The constraint of an `Item`, `None` for any other node. `Expr` implements `Drop`, so a
+    /// `match` on an `Expr` value cannot move it out
+    pub fn into_constraint(mut self) -> Option<Constraint> {
+        match &mut self {
+            Expr::Item(constraint) => Some(mem::replace(constraint, Constraint::vacant())),
+            _ => None,
+        }
+    }
+
+    /// Moves the children out and leaves an `Expr` without children
+    fn take_children(&mut self) -> Vec<Expr> {
+        match self {
+            Expr::Item(_) | Expr::Exists(_) => vec![],
+            Expr::And(children) | Expr::Or(children) => mem::take(children),
+            Expr::Not(child) => vec![mem::replace(&mut **child, Expr::And(vec![]))],
+        }
+    }
+
+    /// Joins `exprs` with `op` into one node, a single expression is returned as it is and
+    /// `None` if `exprs` is empty
+    pub fn join<I: IntoIterator<Item = Expr>>(op: Operator, exprs: I) -> Option<Expr> {
+        let mut exprs = exprs.into_iter().collect::<Vec<_>>();
+        match exprs.len() {
+            0 => None,
+            1 => exprs.pop(),
+            _ => Some(match op {
+                Operator::And => Expr::And(exprs),
+                Operator::Or => Expr::Or(exprs),
+            }),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -55,8 +337,8 @@ mod tests {
     use crate::ast::comparison::Comparison;
     use crate::ast::constraint::Constraint;
     use crate::ast::expr::Expr;
-    use crate::ast::Operator;
     use crate::ParserResult;
+    use std::convert::TryInto;
 
     #[test]
     fn test_node() -> ParserResult<()> {
@@ -67,9 +349,8 @@ mod tests {
         let const4 =
             Expr::Item(Constraint::new("select4", Comparison::IN(), &["test4a", "test4b"])?);
 
-        let node1 = Expr::Node(Operator::And, Box::new(const1), Box::new(const3));
-        let node2 = Expr::Node(Operator::Or, Box::new(const2), Box::new(const4));
-        let root = Expr::Node(Operator::And, Box::new(node1), Box::new(node2));
+        let node = Expr::Or(vec![const2, const4]);
+        let root = Expr::And(vec![const1, const3, node]);
 
         assert_eq!(
             root.to_string(),
@@ -78,4 +359,64 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_serde() -> anyhow::Result<()> {
+        let root = Expr::Or(vec![
+            Expr::And(vec![
+                Expr::item("a", Comparison::EQUAL(), &["1"])?,
+                Expr::item("b", Comparison::EQUAL(), &["2"])?,
+            ]),
+            Expr::item("c", Comparison::IN(), &["3", "4"])?,
+        ]);
+        let json = serde_json::to_value(&root)?;
+        assert_eq!(json["@type"], "Or");
+        assert_eq!(json["@data"][0]["@type"], "And");
+        assert_eq!(json["@data"][0]["@data"].as_array().map(Vec::len), Some(2));
+        assert_eq!(serde_json::from_value::<Expr>(json)?, root);
+        assert_eq!(root.to_string(), "a==1;b==2,c=in=(3,4)");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_not() -> anyhow::Result<()> {
+        let a = Expr::item("a", Comparison::EQUAL(), &["1"])?;
+        let b = Expr::item("b", Comparison::EQUAL(), &["2"])?;
+        let root = Expr::Or(vec![
+            Expr::Not(Box::new(a.clone())),
+            Expr::Not(Box::new(Expr::And(vec![a, b]))),
+        ]);
+        assert_eq!(root.to_string(), "!a==1,!(a==1;b==2)");
+
+        let json = serde_json::to_value(&root)?;
+        assert_eq!(json["@data"][1]["@type"], "Not");
+        assert_eq!(json["@data"][1]["@data"]["@type"], "And");
+        assert_eq!(serde_json::from_value::<Expr>(json)?, root);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_children() -> anyhow::Result<()> {
+        let a = Expr::item("a", Comparison::EQUAL(), &["1"])?;
+        let b = Expr::item("b", Comparison::EQUAL(), &["2"])?;
+        let root = Expr::Or(vec![Expr::Not(Box::new(a.clone())), b.clone()]);
+
+        let [not, last]: [Expr; 2] = root.into_children().try_into().expect("two children");
+        assert_eq!(last, b);
+        assert_eq!(not.into_children(), vec![a]);
+        assert!(b.into_children().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_constraint() -> anyhow::Result<()> {
+        let constraint = Constraint::new("a", Comparison::EQUAL(), &["1"])?;
+        assert_eq!(Expr::Item(constraint.clone()).into_constraint(), Some(constraint));
+        assert_eq!(Expr::Exists("a".to_string()).into_constraint(), None);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,395 @@
+use crate::ast::expr::needs_parens;
+use crate::Expr;
+use crate::QueryType;
+use std::fmt;
+use std::fmt::Write;
+
+/// Which of the symbols of a comparison is written
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ComparisonStyle {
+    /// The first symbol, e.g. `=ge=` for `GREATER_THAN_OR_EQUAL`
+    First,
+    /// The first `=word=`, `==` or `!=` symbol
+    Fiql,
+    /// The first symbol without letters, e.g. `>=`
+    Symbolic,
+}
+
+/// How RSQL arguments that need quotes are quoted
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum QuoteStyle {
+    /// The quote that needs fewer escapes, `'` on a tie
+    Shortest,
+    Single,
+    Double,
+}
+
+/// Output style of the AST. `Display` writes with `Formatter::default()`, `to_fiql_string`
+/// with `Formatter::fiql()`.
+///
+/// FIQL has no keywords, whitespace, line breaks or quotes, so those options are ignored for
+/// FIQL. It has no negation either, formatting an `Expr::Not` in FIQL fails with `fmt::Error`,
+/// as does formatting an `Expr::Exists` or a selector with whitespace or reserved chars in RSQL.
+/// `Display` never fails, it writes such nodes as close as it can, which the dialect cannot
+/// parse back.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Formatter {
+    pub(crate) query_type: QueryType,
+    pub(crate) keyword_operators: bool,
+    pub(crate) comparison_style: ComparisonStyle,
+    pub(crate) quote_style: QuoteStyle,
+    pub(crate) spaces: bool,
+    pub(crate) max_width: Option<usize>,
+    pub(crate) indent: usize,
+    /// Writes nodes the style has no syntax for instead of failing, see `Formatter::lossy`
+    pub(crate) lossy: bool,
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Formatter::new(QueryType::Rsql)
+    }
+}
+
+impl Formatter {
+    pub fn new(query_type: QueryType) -> Self {
+        Formatter {
+            query_type,
+            keyword_operators: false,
+            comparison_style: ComparisonStyle::First,
+            quote_style: QuoteStyle::Shortest,
+            spaces: false,
+            max_width: None,
+            indent: 2,
+            lossy: false,
+        }
+    }
+
+    pub fn rsql() -> Self {
+        Formatter::new(QueryType::Rsql)
+    }
+
+    pub fn fiql() -> Self {
+        Formatter::new(QueryType::Fiql)
+    }
+
+    /// `and`, `or` and `not ` instead of `;`, `,` and `!`, always with spaces around
+    pub fn keyword_operators(mut self, enabled: bool) -> Self {
+        self.keyword_operators = enabled;
+        self
+    }
+
+    pub fn comparison_style(mut self, style: ComparisonStyle) -> Self {
+        self.comparison_style = style;
+        self
+    }
+
+    pub fn quote_style(mut self, style: QuoteStyle) -> Self {
+        self.quote_style = style;
+        self
+    }
+
+    /// Spaces around `;` and `,`
+    pub fn spaces(mut self, enabled: bool) -> Self {
+        self.spaces = enabled;
+        self
+    }
+
+    /// Writes an `Expr` over several lines, each group that does not fit in `width` columns
+    /// with one operand per line and its nested groups indented. Constraints are not wrapped,
+    /// so a line can exceed `width` if a single constraint does. The output stays valid RSQL
+    pub fn max_width(mut self, width: usize) -> Self {
+        self.max_width = Some(width);
+        self
+    }
+
+    /// Spaces per level of nesting when `max_width` breaks lines, 2 by default
+    pub fn indent(mut self, spaces: usize) -> Self {
+        self.indent = spaces;
+        self
+    }
+
+    /// Writes `node` in this style, `fmt::Error` if the style has no syntax for it
+    pub fn format<T: Format + ?Sized>(&self, node: &T) -> Result<String, fmt::Error> {
+        let mut out = String::new();
+        node.write_to(&mut out, self)?;
+        Ok(out)
+    }
+
+    /// Wraps `node` so that `Display` writes it in this style
+    pub fn display<'a, T: Format + ?Sized>(&'a self, node: &'a T) -> Formatted<'a, T> {
+        Formatted { formatter: self, node }
+    }
+
+    /// The style `Display` writes in, which only fails if the writer does
+    pub(crate) fn lossy(mut self) -> Self {
+        self.lossy = true;
+        self
+    }
+
+    pub(crate) fn is_fiql(&self) -> bool {
+        matches!(self.query_type, QueryType::Fiql)
+    }
+
+    pub(crate) fn and(&self) -> &'static str {
+        match (self.is_fiql(), self.keyword_operators, self.spaces) {
+            (true, _, _) | (false, false, false) => ";",
+            (false, true, _) => " and ",
+            (false, false, true) => " ; ",
+        }
+    }
+
+    pub(crate) fn or(&self) -> &'static str {
+        match (self.is_fiql(), self.keyword_operators, self.spaces) {
+            (true, _, _) | (false, false, false) => ",",
+            (false, true, _) => " or ",
+            (false, false, true) => " , ",
+        }
+    }
+
+    pub(crate) fn not(&self) -> &'static str {
+        if self.keyword_operators && !self.is_fiql() {
+            "not "
+        } else {
+            "!"
+        }
+    }
+}
+
+/// A node of the AST that can be written in the style of a `Formatter`
+pub trait Format {
+    fn write_to(&self, f: &mut dyn fmt::Write, formatter: &Formatter) -> fmt::Result;
+}
+
+/// `Display` of a node in the style of a `Formatter`, see `Formatter::display`
+pub struct Formatted<'a, T: ?Sized> {
+    formatter: &'a Formatter,
+    node: &'a T,
+}
+
+impl<T: Format + ?Sized> fmt::Display for Formatted<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.node.write_to(f, &self.formatter.clone().lossy())
+    }
+}
+
+/// Tracks the column the next character is written at
+struct Columns<'a> {
+    inner: &'a mut dyn fmt::Write,
+    column: usize,
+}
+
+impl fmt::Write for Columns<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.column = match s.rfind('\n') {
+            Some(idx) => s[idx + 1..].chars().count(),
+            None => self.column + s.chars().count(),
+        };
+        self.inner.write_str(s)
+    }
+}
+
+/// Fails as soon as more than `room` characters are written, so that measuring a large node
+/// stops early
+struct Measure {
+    room: usize,
+}
+
+impl fmt::Write for Measure {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.room = self.room.checked_sub(s.chars().count()).ok_or(fmt::Error)?;
+        Ok(())
+    }
+}
+
+/// Whether `expr` fits in `room` columns on one line
+fn fits(expr: &Expr, flat: &Formatter, room: usize) -> bool {
+    expr.write_to(&mut Measure { room }, flat).is_ok()
+}
+
+/// Writes `expr` over several lines, see `Formatter::max_width`
+pub(crate) fn write_lines(
+    expr: &Expr, f: &mut dyn fmt::Write, formatter: &Formatter, width: usize,
+) -> fmt::Result {
+    enum Step<'e> {
+        /// A node at a nesting depth, followed by `trailing` columns on its last line
+        Expr {
+            expr: &'e Expr,
+            depth: usize,
+            trailing: usize,
+        },
+        /// A node in parentheses, which are left out if `optional` and the node fits
+        Group {
+            expr: &'e Expr,
+            depth: usize,
+            trailing: usize,
+            optional: bool,
+        },
+        Str(&'static str),
+        Break(usize),
+    }
+
+    let flat = Formatter { max_width: None, ..formatter.clone() };
+    let f = &mut Columns { inner: f, column: 0 };
+    // Explicit stack, so that deeply nested expressions cannot overflow the call stack
+    let mut stack = vec![Step::Expr { expr, depth: 0, trailing: 0 }];
+    while let Some(step) = stack.pop() {
+        let (expr, depth, trailing) = match step {
+            Step::Str(s) => {
+                f.write_str(s)?;
+                continue;
+            }
+            Step::Break(depth) => {
+                write!(f, "\n{:1$}", "", depth * formatter.indent)?;
+                continue;
+            }
+            Step::Group { expr, depth, trailing, optional } => {
+                let room = width.saturating_sub(f.column + trailing);
+                if optional && fits(expr, &flat, room) {
+                    expr.write_to(f, &flat)?;
+                } else if fits(expr, &flat, room.saturating_sub(2)) {
+                    write!(f, "({})", flat.display(expr))?;
+                } else {
+                    stack.push(Step::Str(")"));
+                    stack.push(Step::Break(depth));
+                    stack.push(Step::Expr { expr, depth: depth + 1, trailing: 0 });
+                    stack.push(Step::Break(depth + 1));
+                    stack.push(Step::Str("("));
+                }
+                continue;
+            }
+            Step::Expr { expr, depth, trailing } => (expr, depth, trailing),
+        };
+        if fits(expr, &flat, width.saturating_sub(f.column + trailing)) {
+            expr.write_to(f, &flat)?;
+            continue;
+        }
+        let (op, children) = match expr {
+            Expr::Item(_) | Expr::Exists(_) => {
+                expr.write_to(f, &flat)?;
+                continue;
+            }
+            Expr::And(children) => (flat.and().trim_end(), &children[..]),
+            Expr::Or(children) => (flat.or().trim_end(), &children[..]),
+            Expr::Not(child) => {
+                f.write_str(flat.not())?;
+                ("", std::slice::from_ref(&**child))
+            }
+        };
+        for (idx, child) in children.iter().enumerate().rev() {
+            let trailing = if idx + 1 == children.len() { trailing } else { op.chars().count() };
+            // Bare groups are put in parentheses when broken, so that the lines show precedence
+            stack.push(match child {
+                Expr::And(_) | Expr::Or(_) => Step::Group {
+                    expr: child,
+                    depth,
+                    trailing,
+                    optional: !needs_parens(expr, child),
+                },
+                _ => Step::Expr { expr: child, depth, trailing },
+            });
+            if idx > 0 {
+                stack.push(Step::Break(depth));
+                stack.push(Step::Str(op));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Arity, Comparison, ComparisonStyle, Expr, Formatter, ParserResult, QuoteStyle};
+    use std::fmt;
+
+    fn query() -> ParserResult<Expr> {
+        let year = Expr::item("year", Comparison::GREATER_THAN_OR_EQUAL(), &["2000"])?;
+        let genre = Expr::item("genre", Comparison::IN(), &["sci-fi", "drama"])?;
+        let title = Expr::item("title", Comparison::NOT_EQUAL(), &["it's"])?;
+        let or = Expr::Or(vec![genre, Expr::Not(Box::new(title))]);
+        Ok(Expr::And(vec![year, or]))
+    }
+
+    #[test]
+    fn test_styles() -> anyhow::Result<()> {
+        let query = query()?;
+        assert_eq!(query.to_string(), r#"year=ge=2000;(genre=in=(sci-fi,drama),!title!="it's")"#);
+        let formatter = Formatter::default()
+            .keyword_operators(true)
+            .comparison_style(ComparisonStyle::Symbolic)
+            .quote_style(QuoteStyle::Single);
+        assert_eq!(
+            formatter.format(&query)?,
+            r#"year>=2000 and (genre=in=(sci-fi,drama) or not title!='it\'s')"#
+        );
+        let formatter = Formatter::default().spaces(true);
+        assert_eq!(
+            formatter.display(&query).to_string(),
+            r#"year=ge=2000 ; (genre=in=(sci-fi,drama) , !title!="it's")"#
+        );
+        let formatter = Formatter::fiql()
+            .keyword_operators(true)
+            .comparison_style(ComparisonStyle::Symbolic)
+            .spaces(true);
+        assert_eq!(formatter.format(&query), Err(fmt::Error));
+        assert_eq!(query.to_fiql_string(), Err(fmt::Error));
+        // `Display` writes the negation as in RSQL
+        assert_eq!(
+            formatter.display(&query).to_string(),
+            "year=ge=2000;(genre=in=(sci-fi,drama),!title!=it's)"
+        );
+
+        let query = Expr::And(vec![
+            Expr::item("year", Comparison::GREATER_THAN_OR_EQUAL(), &["2000"])?,
+            Expr::item("title", Comparison::NOT_EQUAL(), &["it's"])?,
+        ]);
+        assert_eq!(formatter.format(&query), query.to_fiql_string());
+        assert_eq!(formatter.max_width(10).format(&query), query.to_fiql_string());
+        assert_eq!(query.to_fiql_string()?, "year=ge=2000;title!=it's");
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_width() -> anyhow::Result<()> {
+        let query =
+            Expr::Or(vec![query()?, Expr::item("status", Comparison::EQUAL(), &["released"])?]);
+        let formatter = Formatter::default().max_width(30);
+        assert_eq!(
+            formatter.format(&query)?,
+            r#"(
+  year=ge=2000;
+  (
+    genre=in=(sci-fi,drama),
+    !title!="it's"
+  )
+),
+status==released"#
+        );
+        let formatter = formatter.max_width(60).indent(4).keyword_operators(true);
+        assert_eq!(
+            formatter.format(&query)?,
+            r#"(
+    year=ge=2000 and
+    (genre=in=(sci-fi,drama) or not title!="it's")
+) or
+status==released"#
+        );
+        assert_eq!(
+            formatter.clone().max_width(100).format(&query),
+            Formatter::default().keyword_operators(true).format(&query)
+        );
+        assert_eq!(Formatter::fiql().max_width(10).format(&query), Err(fmt::Error));
+        Ok(())
+    }
+
+    #[test]
+    fn test_comparison_style() -> ParserResult<()> {
+        let like = Comparison::new(&["~=", "=like="], Arity::Exact(1))?;
+        assert_eq!(like.symbol(ComparisonStyle::First), "~=");
+        assert_eq!(like.symbol(ComparisonStyle::Fiql), "=like=");
+        assert_eq!(Comparison::IN().symbol(ComparisonStyle::Symbolic), "=in=");
+        assert_eq!(Comparison::NOT_EQUAL().symbol(ComparisonStyle::Fiql), "!=");
+        assert_eq!(Comparison::LESS_THAN().symbol(ComparisonStyle::Symbolic), "<");
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
+pub mod borrowed;
 pub mod comparison;
 pub mod constraint;
 pub mod expr;
+pub mod format;
+pub mod value;
 
 use serde::{Deserialize, Serialize};
 
@@ -0,0 +1,278 @@
+use crate::ast::constraint::fiql_argument;
+use crate::error::ParserError;
+use crate::ParserResult;
+use crate::{Format, Formatter, QuoteStyle};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// An argument of a constraint.
+///
+/// Quoted arguments are always `String`. Unquoted ones are `Unquoted` unless
+/// `ParserOptions::typed_values` is enabled, which recognizes `null`, `true`, `false`, integers
+/// and floats written the way they are printed, e.g. `2003` and `1.5` but not `02003` or `1.50`.
+///
+/// Neither dialect has a syntax for non-finite floats, `Constraint::new`, `Value::try_from` and
+/// deserializing reject them.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "@type", content = "@data", try_from = "ValueRepr")]
+pub enum Value {
+    Unquoted(String),
+    String(String),
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "@type", content = "@data")]
+enum ValueRepr {
+    Unquoted(String),
+    String(String),
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+}
+
+impl TryFrom<ValueRepr> for Value {
+    type Error = ParserError;
+
+    fn try_from(repr: ValueRepr) -> ParserResult<Self> {
+        Ok(match repr {
+            ValueRepr::Unquoted(text) => Value::Unquoted(text),
+            ValueRepr::String(text) => Value::String(text),
+            ValueRepr::Null => Value::Null,
+            ValueRepr::Bool(value) => Value::Bool(value),
+            ValueRepr::Integer(value) => Value::Integer(value),
+            ValueRepr::Float(value) => Value::try_from(value)?,
+        })
+    }
+}
+
+static RESERVED_CHARS: &[char] = &['"', '\'', '(', ')', ';', ',', '=', '!', '~', '<', '>'];
+
+/// Whether RSQL can write `text` without quotes, which is also what a selector can be
+pub(crate) fn is_bare(text: &str) -> bool {
+    let is_space = |c: char| matches!(c, ' ' | '\t' | '\r' | '\n');
+    !text.is_empty() && !text.contains(|c: char| RESERVED_CHARS.contains(&c) || is_space(c))
+}
+
+/// Quotes `arg` in `style`
+fn quote(arg: &str, style: QuoteStyle) -> String {
+    let escapes = |quote: char| arg.chars().filter(|&c| c == quote).count();
+    let quote = match style {
+        QuoteStyle::Shortest if escapes('"') < escapes('\'') => '"',
+        QuoteStyle::Shortest | QuoteStyle::Single => '\'',
+        QuoteStyle::Double => '"',
+    };
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push(quote);
+    for c in arg.chars() {
+        if c == quote || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push(quote);
+    quoted
+}
+
+impl Value {
+    /// The typed value `text` stands for, if it is written the way that value is printed
+    pub fn recognize(text: &str) -> Option<Value> {
+        let value = match text {
+            "null" => Value::Null,
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ if text.contains(['.', 'e', 'E']) => Value::Float(text.parse().ok()?),
+            _ => Value::Integer(text.parse().ok()?),
+        };
+        Some(value).filter(|value| value.text() == text)
+    }
+
+    /// Fails for a non-finite `Float`
+    pub(crate) fn check(&self) -> ParserResult<()> {
+        match self {
+            Value::Float(value) if !value.is_finite() => {
+                Err(ParserError::InvalidValue(self.text().into_owned()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The text of an `Unquoted` or `String` value
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Unquoted(text) | Value::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// The value without quotes or escapes
+    pub fn text(&self) -> Cow<'_, str> {
+        match self {
+            Value::Unquoted(text) | Value::String(text) => Cow::Borrowed(text),
+            Value::Null => Cow::Borrowed("null"),
+            Value::Bool(value) => Cow::Owned(value.to_string()),
+            Value::Integer(value) => Cow::Owned(value.to_string()),
+            Value::Float(value) => Cow::Owned(format!("{:?}", value)),
+        }
+    }
+}
+
+/// Floats are compared by their bits, so that `Value` is `Eq`
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Unquoted(a), Value::Unquoted(b)) | (Value::String(a), Value::String(b)) => {
+                a == b
+            }
+            (Value::Null, Value::Null) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_to(f, &Formatter::default().lossy())
+    }
+}
+
+/// In RSQL strings are always quoted, only quotes and backslashes are escaped so that any text
+/// reads back unchanged. FIQL has no quotes, the text is percent-encoded instead.
+///
+/// Values that would read back as another variant fail with `fmt::Error`: `Unquoted` ones RSQL
+/// cannot write without quotes, e.g. text with whitespace decoded from FIQL, and non-finite
+/// floats built without the checks. `Display` quotes the former and writes the latter as `NaN`
+/// or `inf`
+impl Format for Value {
+    fn write_to(&self, f: &mut dyn fmt::Write, formatter: &Formatter) -> fmt::Result {
+        match self {
+            Value::Float(value) if !value.is_finite() && !formatter.lossy => Err(fmt::Error),
+            _ if formatter.is_fiql() => f.write_str(&fiql_argument(&self.text())),
+            Value::String(text) => f.write_str(&quote(text, formatter.quote_style)),
+            Value::Unquoted(text) if !is_bare(text) && !formatter.lossy => Err(fmt::Error),
+            Value::Unquoted(text) if !is_bare(text) => {
+                f.write_str(&quote(text, formatter.quote_style))
+            }
+            _ => f.write_str(&self.text()),
+        }
+    }
+}
+
+/// `Unquoted` if RSQL can write `text` without quotes, else `String`, so that the value is
+/// written either way
+impl From<&str> for Value {
+    fn from(text: &str) -> Self {
+        Value::from(text.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Self {
+        if is_bare(&text) {
+            Value::Unquoted(text)
+        } else {
+            Value::String(text)
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value)
+    }
+}
+
+impl TryFrom<f64> for Value {
+    type Error = ParserError;
+
+    fn try_from(value: f64) -> ParserResult<Self> {
+        let value = Value::Float(value);
+        value.check()?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Format, Formatter, QuoteStyle, Value};
+    use std::fmt;
+
+    fn string(text: &str) -> Value {
+        Value::String(text.to_string())
+    }
+
+    #[test]
+    fn test_recognize() {
+        assert_eq!(Value::recognize("null"), Some(Value::Null));
+        assert_eq!(Value::recognize("true"), Some(Value::Bool(true)));
+        assert_eq!(Value::recognize("-2003"), Some(Value::Integer(-2003)));
+        assert_eq!(Value::recognize("1.5"), Some(Value::Float(1.5)));
+        assert_eq!(Value::recognize("1e300"), Some(Value::Float(1e300)));
+        for text in
+            &["NULL", "02003", "+1", "1.50", "1e3", "2.", "inf", "NaN", "99999999999999999999"]
+        {
+            assert_eq!(Value::recognize(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_to_string() {
+        assert_eq!(Value::String("2003".to_string()).to_string(), "'2003'");
+        assert_eq!(Value::from("2003").to_string(), "2003");
+        assert_eq!(string("").to_string(), "''");
+        assert_eq!(string("a\tb").to_string(), "'a\tb'");
+        assert_eq!(string(r#"it's 'a' "b""#).to_string(), r#""it's 'a' \"b\"""#);
+        assert_eq!(string(r#"a\"b"#).to_string(), r#"'a\\"b'"#);
+        assert_eq!(Value::Float(2.0).to_string(), "2.0");
+        assert_eq!(Value::Null.to_string(), "null");
+    }
+
+    #[test]
+    fn test_unwritable() {
+        let rsql = Formatter::rsql();
+        // Quoted, they would read back as strings
+        for text in &["a b", "", "it's", "a;b"] {
+            let value = Value::Unquoted(text.to_string());
+            assert_eq!(rsql.format(&value), Err(fmt::Error), "{}", text);
+            assert_eq!(Value::from(*text), string(text));
+        }
+        let value = Value::Unquoted("a b".to_string());
+        assert_eq!(Formatter::fiql().format(&value).as_deref(), Ok("a%20b"));
+        for value in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(Value::Float(*value).write_to(&mut String::new(), &rsql).is_err());
+            assert_eq!(Formatter::fiql().format(&Value::Float(*value)), Err(fmt::Error));
+        }
+        // `Display` writes them anyway
+        assert_eq!(Value::Unquoted("a b".to_string()).to_string(), "'a b'");
+        assert_eq!(Value::Float(f64::NEG_INFINITY).to_string(), "-inf");
+        assert_eq!(Formatter::fiql().display(&Value::Float(f64::NAN)).to_string(), "NaN");
+    }
+
+    #[test]
+    fn test_quote_style() {
+        let format =
+            |style, value| Formatter::default().quote_style(style).display(&value).to_string();
+        assert_eq!(format(QuoteStyle::Shortest, string("it's")), r#""it's""#);
+        assert_eq!(format(QuoteStyle::Single, string("it's")), r#"'it\'s'"#);
+        assert_eq!(format(QuoteStyle::Double, string(r#"a "b""#)), r#""a \"b\"""#);
+        assert_eq!(format(QuoteStyle::Double, Value::from("a")), "a");
+        assert_eq!(Formatter::fiql().display(&string(r#"it's "b""#)).to_string(), "it's%20%22b%22");
+    }
+}
@@ -1,4 +1,10 @@
-use crate::{ParserResult, QueryType};
+use crate::span::{LineIndex, Position};
+use crate::{Arity, QueryType};
+use itertools::Itertools;
+use pest::error::{ErrorVariant, InputLocation};
+use pest::RuleType;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,26 +12,124 @@ pub enum ParserError {
     #[error("Empty symbol array when creating a new Comparison")]
     EmptySymbol(),
 
-    #[error("Invalid Pair Rule found")]
-    InvalidPairRule(),
+    #[error("{0}")]
+    Syntax(SyntaxError),
 
     #[error("Invalid Comparison found: {0}")]
     InvalidComparison(String),
+    #[error("Invalid Value found: {0}")]
+    InvalidValue(String),
+    #[error("Comparison symbol {0} is not read as a whole by the dialect")]
+    AmbiguousComparison(String),
+    #[error("Comparison symbol {0} is already registered for another Comparison")]
+    ConflictingComparison(String),
+    #[error("Percent-encoded value is not valid UTF-8: {0}")]
+    InvalidPercentEncoding(String),
     #[error("Invalid Query found: {0}")]
     InvalidQuery(QueryType),
 
-    #[error("Invalid Constraint arguments: expect: {0}, found: {1}")]
-    InvalidConstraintArgs(String, usize),
+    #[error("Invalid Constraint arguments: expected {expected}, found {count}")]
+    InvalidConstraintArgs { expected: Arity, count: usize },
     #[error("Cannot find {field} when constructing {ty}")]
     LackOfField { ty: String, field: String },
 
+    #[error("Query is {length} bytes long, at most {max} are allowed")]
+    InputTooLong { max: usize, length: usize },
+    #[error("Query nests deeper than {max} levels at offset {offset}")]
+    NestingTooDeep { max: usize, offset: usize },
+    #[error("Query has more than {max} constraints")]
+    TooManyConstraints { max: usize },
+    #[error("Constraint has {count} arguments, at most {max} are allowed")]
+    TooManyArguments { max: usize, count: usize },
+    #[error("Argument is {length} bytes long, at most {max} are allowed")]
+    ArgumentTooLong { max: usize, length: usize },
+
     #[error("Unhandled Error: {0}")]
     Unhandled(#[source] anyhow::Error),
 }
 
-impl ParserError {
-    pub fn invalid_pair_rule<T>() -> ParserResult<T> {
-        Err(ParserError::InvalidPairRule())
+/// A dialect-neutral description of a token the parser was looking for
+#[derive(
+    Display, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone, Copy, Hash,
+)]
+pub enum Expected {
+    #[strum(to_string = "selector")]
+    Selector,
+    #[strum(to_string = "comparison operator")]
+    ComparisonOperator,
+    #[strum(to_string = "argument")]
+    Argument,
+    #[strum(to_string = "logical operator")]
+    LogicalOperator,
+    #[strum(to_string = "opening parenthesis")]
+    OpeningParenthesis,
+    #[strum(to_string = "closing parenthesis")]
+    ClosingParenthesis,
+    #[strum(to_string = "end of input")]
+    EndOfInput,
+}
+
+/// The query does not match the grammar of the dialect
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct SyntaxError {
+    pub position: Position,
+    /// The offending input at `position`, empty if the input ended unexpectedly
+    pub snippet: String,
+    /// What would have been accepted at `position`, empty if nothing specific was expected
+    pub expected: Vec<Expected>,
+}
+
+const SNIPPET_MAX_CHARS: usize = 20;
+
+impl SyntaxError {
+    pub fn new(code: &str, offset: usize, expected: Vec<Expected>) -> Self {
+        let snippet = code[offset..]
+            .chars()
+            .take_while(|&c| c != '\n' && c != '\r')
+            .take(SNIPPET_MAX_CHARS)
+            .collect();
+        let expected = expected.into_iter().sorted().dedup().collect();
+        SyntaxError { position: LineIndex::new(code).position(offset), snippet, expected }
+    }
+
+    /// Converts a pest error, `expected` maps the attempted rules of the grammar
+    pub(crate) fn from_pest<R: RuleType>(
+        err: pest::error::Error<R>, code: &str, expected: impl Fn(R) -> &'static [Expected],
+    ) -> Self {
+        SyntaxError::from_pest_at(err, code, 0, expected)
+    }
+
+    /// Same as `from_pest`, for an error found when parsing `code[start..]`
+    pub(crate) fn from_pest_at<R: RuleType>(
+        err: pest::error::Error<R>, code: &str, start: usize,
+        expected: impl Fn(R) -> &'static [Expected],
+    ) -> Self {
+        let offset = start
+            + match err.location {
+                InputLocation::Pos(offset) => offset,
+                InputLocation::Span((start, _)) => start,
+            };
+        let expected = match err.variant {
+            ErrorVariant::ParsingError { positives, .. } => {
+                positives.into_iter().flat_map(|rule| expected(rule).iter().cloned()).collect()
+            }
+            ErrorVariant::CustomError { .. } => vec![],
+        };
+        SyntaxError::new(code, offset, expected)
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Syntax error at {}:{}", self.position.line, self.position.column)?;
+        if !self.expected.is_empty() {
+            write!(f, ", expected {}", self.expected.iter().join(" or "))?;
+        }
+        if self.snippet.is_empty() {
+            write!(f, ", found end of input")
+        } else {
+            write!(f, ", found `{}`", self.snippet)
+        }
     }
 }
 
@@ -37,15 +141,3 @@ impl From<anyhow::Error> for ParserError {
         }
     }
 }
-
-impl From<pest::error::Error<crate::parser::fiql::Rule>> for ParserError {
-    fn from(err: pest::error::Error<crate::parser::fiql::Rule>) -> Self {
-        ParserError::Unhandled(anyhow::Error::from(err))
-    }
-}
-
-impl From<pest::error::Error<crate::parser::rsql::Rule>> for ParserError {
-    fn from(err: pest::error::Error<crate::parser::rsql::Rule>) -> Self {
-        ParserError::Unhandled(anyhow::Error::from(err))
-    }
-}
@@ -7,16 +7,15 @@ extern crate strum_macros;
 
 use crate::error::ParserError;
 
-#[macro_use]
-pub mod macros;
 mod ast;
-pub use ast::{comparison::*, constraint::*, expr::*, Operator};
+pub use ast::{borrowed, comparison::*, constraint::*, expr::*, format::*, value::*, Operator};
 pub mod error;
 pub mod parser;
+pub mod span;
 
 pub(crate) type ParserResult<T> = std::result::Result<T, ParserError>;
 
-#[derive(Display, Debug)]
+#[derive(Display, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum QueryType {
     Fiql,
     Rsql,
@@ -0,0 +1,86 @@
+//! The extension point for query languages.
+//!
+//! A `Dialect` only splits a query into tokens and decodes the values it finds, `QueryParser`
+//! does everything else: looking up comparisons, checking arities and limits, matching groups
+//! and building the `Expr`.
+
+use crate::borrowed::Value;
+use crate::error::{ParserError, SyntaxError};
+use crate::parser::options::ParserOptions;
+use crate::{Comparison, Operator};
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::Arc;
+
+pub trait Dialect {
+    /// Splits `code` into tokens, or returns the first syntax error.
+    ///
+    /// With `recovering`, input the dialect cannot read should become `TokenKind::Invalid`
+    /// tokens instead, so that `Parser::parse_recovering` can carry on after it.
+    fn tokenize(
+        &self, code: &str, options: &ParserOptions, recovering: bool,
+    ) -> Result<Vec<Token>, ParserError>;
+
+    /// The built-in comparison written as `symbol`, shared by the constraints that use it
+    fn default_comparison(&self, symbol: &str) -> Option<Arc<Comparison>>;
+
+    /// Whether `symbol` is read as a whole comparison between a selector and a value, symbols
+    /// of registered comparisons must be
+    fn reads_comparison(&self, symbol: &str, options: &ParserOptions) -> bool {
+        let code = format!("s{}v", symbol);
+        match self.tokenize(&code, options, false).as_deref() {
+            Ok([Token { kind: TokenKind::Constraint(constraint), .. }]) => {
+                constraint.comparison == (1..1 + symbol.len())
+            }
+            _ => false,
+        }
+    }
+
+    /// Decodes a selector as written in the query
+    fn decode_selector<'i>(
+        &self, selector: &'i str, _options: &ParserOptions,
+    ) -> Result<Cow<'i, str>, ParserError> {
+        Ok(Cow::Borrowed(selector))
+    }
+
+    /// Decodes an argument value as written in the query, quotes included. Types are
+    /// recognized by the parser afterwards, see `ParserOptions::typed_values`
+    fn decode_value<'i>(
+        &self, value: &'i str, _options: &ParserOptions,
+    ) -> Result<Value<'i>, ParserError> {
+        Ok(Value::Unquoted(Cow::Borrowed(value)))
+    }
+}
+
+/// A token of a query, `range` is its byte range in the query
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub range: Range<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TokenKind {
+    Constraint(ConstraintToken),
+    /// A bare selector, see `Expr::Exists`
+    Exists,
+    /// A logical operator. Keywords are checked against `ParserOptions` by the parser
+    Operator(Operator),
+    /// A negation prefix, checked against `ParserOptions` by the parser
+    Not,
+    Open,
+    Close,
+    /// Input the dialect cannot read
+    Invalid(SyntaxError),
+}
+
+/// Byte ranges of the parts of a constraint
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ConstraintToken {
+    pub selector: Range<usize>,
+    pub comparison: Range<usize>,
+    /// The whole argument, parentheses of a list included
+    pub arguments: Range<usize>,
+    /// Every argument value, as passed to `Dialect::decode_value`
+    pub values: Vec<Range<usize>>,
+}
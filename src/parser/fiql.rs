@@ -1,31 +1,134 @@
-use crate::Arguments;
+use crate::ast::comparison::shared;
+use crate::borrowed::Value;
+use crate::error::{Expected, ParserError};
+use crate::parser::dialect::{Dialect, Token};
+use crate::parser::grammar::{self, Grammar, Role};
+use crate::parser::options::ParserOptions;
+use crate::parser::QueryParser;
 use crate::Comparison;
+use crate::ParserResult;
+use grammar_impl::{FiqlGrammar, Rule};
+use std::borrow::Cow;
+use std::sync::Arc;
 
-use crate::error::ParserError;
-use crate::parser::Parser;
+mod grammar_impl {
+    #[derive(Parser)]
+    #[grammar = "fiql.pest"]
+    pub struct FiqlGrammar;
+}
 
-use pest::iterators::Pair;
-use pest::Parser as PestParser;
+/// FIQL as in the draft, plus argument lists in parentheses for multi-value comparisons
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Fiql;
 
-use std::collections::HashMap;
-use std::convert::{TryFrom, TryInto};
+pub type FiqlParser = QueryParser<Fiql>;
 
-#[derive(Parser, Default)]
-#[grammar = "fiql.pest"]
-pub struct FiqlParser(HashMap<String, Comparison>);
-gen_parser!(FiqlParser);
+impl Dialect for Fiql {
+    fn tokenize(
+        &self, code: &str, options: &ParserOptions, recovering: bool,
+    ) -> Result<Vec<Token>, ParserError> {
+        grammar::tokenize::<Rule, FiqlGrammar>(code, options, recovering)
+    }
 
-impl Parser for FiqlParser {
-    type R = Rule;
+    fn default_comparison(&self, symbol: &str) -> Option<Arc<Comparison>> {
+        let comparison: &Arc<Comparison> = match symbol {
+            "==" => &shared::EQUAL,
+            "!=" => &shared::NOT_EQUAL,
+            "=gt=" => &shared::GREATER_THAN,
+            "=ge=" => &shared::GREATER_THAN_OR_EQUAL,
+            "=lt=" => &shared::LESS_THAN,
+            "=le=" => &shared::LESS_THAN_OR_EQUAL,
+            _ => return None,
+        };
+        Some(Arc::clone(comparison))
+    }
+
+    fn decode_selector<'i>(
+        &self, selector: &'i str, options: &ParserOptions,
+    ) -> Result<Cow<'i, str>, ParserError> {
+        decode(selector, options)
+    }
 
-    gen_basic_parser!(FIQL);
+    fn decode_value<'i>(
+        &self, value: &'i str, options: &ParserOptions,
+    ) -> Result<Value<'i>, ParserError> {
+        decode(value, options).map(Value::Unquoted)
+    }
+}
+
+fn decode<'i>(value: &'i str, options: &ParserOptions) -> ParserResult<Cow<'i, str>> {
+    if options.percent_decoding && value.contains('%') {
+        Ok(Cow::Owned(percent_decode(value)?))
+    } else {
+        Ok(Cow::Borrowed(value))
+    }
 }
 
-impl<'i> TryFrom<Pair<'i, Rule>> for Arguments {
-    type Error = ParserError;
+impl Grammar<Rule> for FiqlGrammar {
+    const QUERY: Rule = Rule::query;
+    const RECOVERY: Rule = Rule::recovery;
+    const SINGLE_CONSTRAINT: Rule = Rule::single_constraint;
+    const QUOTES: bool = false;
+
+    fn role(rule: Rule) -> Role {
+        match rule {
+            Rule::expression => Role::Expression,
+            Rule::constraint => Role::Constraint,
+            Rule::exists => Role::Exists,
+            Rule::selector => Role::Selector,
+            Rule::comparison => Role::Comparison,
+            Rule::argument => Role::Arguments,
+            Rule::value => Role::Value,
+            Rule::operator => Role::Operator,
+            Rule::and_op => Role::And,
+            Rule::open_paren => Role::Open,
+            Rule::close_paren => Role::Close,
+            Rule::invalid => Role::Invalid,
+            _ => Role::Other,
+        }
+    }
+
+    fn expected(rule: Rule) -> &'static [Expected] {
+        expected(rule)
+    }
+}
+
+/// Decodes the `%XX` sequences of `value` into UTF-8
+fn percent_decode(value: &str) -> ParserResult<String> {
+    let hex = |byte: u8| (byte as char).to_digit(16).map(|digit| digit as u8);
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = match tail {
+            [high, low, ..] if byte == b'%' => {
+                hex(*high).and_then(|high| hex(*low).map(|low| high << 4 | low))
+            }
+            _ => None,
+        };
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| ParserError::InvalidPercentEncoding(value.to_string()))
+}
 
-    fn try_from(value: Pair<'i, Rule>) -> Result<Self, Self::Error> {
-        let arg = value.as_str();
-        Ok(Arguments(vec![arg.to_string()]))
+fn expected(rule: Rule) -> &'static [Expected] {
+    match rule {
+        Rule::expression => &[Expected::Selector, Expected::OpeningParenthesis],
+        Rule::constraint | Rule::exists | Rule::selector => &[Expected::Selector],
+        Rule::open_paren => &[Expected::OpeningParenthesis],
+        Rule::close_paren => &[Expected::ClosingParenthesis],
+        Rule::operator | Rule::and_op | Rule::or_op => &[Expected::LogicalOperator],
+        Rule::comparison | Rule::comp_symbol => &[Expected::ComparisonOperator],
+        Rule::argument | Rule::value | Rule::arg_char => &[Expected::Argument],
+        Rule::EOI => &[Expected::EndOfInput],
+        _ => &[],
     }
 }
@@ -0,0 +1,110 @@
+//! Tokenizing with the pest grammars of the built-in dialects
+
+use crate::error::{Expected, ParserError, SyntaxError};
+use crate::parser::dialect::{ConstraintToken, Token, TokenKind};
+use crate::parser::options::{open_groups, ParserOptions};
+use crate::Operator;
+use pest::iterators::Pair;
+use pest::RuleType;
+use std::ops::Range;
+
+/// What a rule of a grammar stands for
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum Role {
+    Expression,
+    Constraint,
+    Exists,
+    Selector,
+    Comparison,
+    Arguments,
+    Value,
+    Operator,
+    And,
+    Not,
+    Open,
+    Close,
+    Invalid,
+    Other,
+}
+
+pub(crate) trait Grammar<R: RuleType>: pest::Parser<R> {
+    /// `SOI ~ expression ~ EOI`, with a flat `expression`
+    const QUERY: R;
+    /// `SOI ~ (constraint | ... | invalid)* ~ EOI`
+    const RECOVERY: R;
+    /// `SOI ~ constraint ~ EOI`, explains what is wrong with `invalid` input
+    const SINGLE_CONSTRAINT: R;
+    /// Whether `'` and `"` start a quoted value
+    const QUOTES: bool;
+
+    fn role(rule: R) -> Role;
+    fn expected(rule: R) -> &'static [Expected];
+}
+
+pub(crate) fn tokenize<R: RuleType, G: Grammar<R>>(
+    code: &str, options: &ParserOptions, recovering: bool,
+) -> Result<Vec<Token>, ParserError> {
+    // Rejects hostile nesting before any token is allocated
+    options.check_depth(code, G::QUOTES)?;
+    let rule = if recovering { G::RECOVERY } else { G::QUERY };
+    let pairs = G::parse(rule, code).map_err(|err| {
+        let mut error = SyntaxError::from_pest(err, code, G::expected);
+        // The grammar accepts a `)` after every constraint, matching is left to the parser
+        if open_groups(&code[..error.position.offset], G::QUOTES) == 0 {
+            error.expected.retain(|e| *e != Expected::ClosingParenthesis);
+        }
+        ParserError::Syntax(error)
+    })?;
+
+    let mut tokens = vec![];
+    for pair in pairs.flat_map(|top| top.into_inner()) {
+        if G::role(pair.as_rule()) == Role::Expression {
+            tokens.extend(pair.into_inner().filter_map(|pair| token::<R, G>(code, pair)));
+        } else {
+            tokens.extend(token::<R, G>(code, pair));
+        }
+    }
+    Ok(tokens)
+}
+
+fn token<R: RuleType, G: Grammar<R>>(code: &str, pair: Pair<'_, R>) -> Option<Token> {
+    let range = range(&pair);
+    let kind = match G::role(pair.as_rule()) {
+        Role::Constraint => {
+            let mut constraint = ConstraintToken {
+                selector: range.clone(),
+                comparison: range.clone(),
+                arguments: range.clone(),
+                values: vec![],
+            };
+            for inner in pair.into_inner().flatten() {
+                match G::role(inner.as_rule()) {
+                    Role::Selector => constraint.selector = self::range(&inner),
+                    Role::Comparison => constraint.comparison = self::range(&inner),
+                    Role::Arguments => constraint.arguments = self::range(&inner),
+                    Role::Value => constraint.values.push(self::range(&inner)),
+                    _ => {}
+                }
+            }
+            TokenKind::Constraint(constraint)
+        }
+        Role::Exists => TokenKind::Exists,
+        Role::Operator => {
+            let is_and = pair.into_inner().any(|op| G::role(op.as_rule()) == Role::And);
+            TokenKind::Operator(if is_and { Operator::And } else { Operator::Or })
+        }
+        Role::Not => TokenKind::Not,
+        Role::Open => TokenKind::Open,
+        Role::Close => TokenKind::Close,
+        Role::Invalid => TokenKind::Invalid(match G::parse(G::SINGLE_CONSTRAINT, pair.as_str()) {
+            Err(err) => SyntaxError::from_pest_at(err, code, range.start, G::expected),
+            Ok(_) => SyntaxError::new(code, range.start, vec![]),
+        }),
+        _ => return None,
+    };
+    Some(Token { kind, range })
+}
+
+fn range<R: RuleType>(pair: &Pair<'_, R>) -> Range<usize> {
+    pair.as_span().start()..pair.as_span().end()
+}
@@ -1,26 +1,339 @@
-use crate::Constraint;
+use crate::borrowed;
+use crate::error::{Expected, ParserError, SyntaxError};
+use crate::parser::dialect::{ConstraintToken, Dialect, Token, TokenKind};
+use crate::parser::options::{stray_whitespace, ParserOptions};
+use crate::parser::recovery::{Diagnostic, Part, PartKind, Recovered};
+use crate::parser::registry::ComparisonRegistry;
+use crate::span::{ConstraintSpan, LineIndex, OperatorSpan, SourceMap, Span};
+use crate::Comparison;
 use crate::Expr;
 use crate::ParserResult;
-use crate::{Comparison, Operator};
-use pest::iterators::Pair;
-use pest::RuleType;
-use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::sync::Arc;
 
+pub mod dialect;
 pub mod fiql;
+mod grammar;
+pub mod options;
+pub mod recovery;
+pub mod registry;
 pub mod rsql;
 
 pub trait Parser {
-    type R: RuleType;
+    /// The registered comparisons, shared as they are
+    fn snapshot(&self) -> Arc<ComparisonRegistry>;
+    /// The comparison `symbol` stands for, shared with the registry and the parsed constraints
+    fn get_comparison(&self, symbol: &str) -> Option<Arc<Comparison>>;
 
-    fn get_inner_mut(&mut self) -> &mut HashMap<String, Comparison>;
-    fn get_inner(&self) -> &HashMap<String, Comparison>;
-    fn register_comparison(&mut self, comparison: &Comparison);
+    /// Registers `comparison` under all of its symbols. A symbol must match the symbol
+    /// patterns of the options and not stand for another comparison yet, and the dialect must
+    /// read at least one of the symbols as a whole, otherwise nothing is registered.
+    ///
+    /// A registry shared with other parsers is copied first, they do not see the change
+    fn register_comparison(&mut self, comparison: &Comparison) -> ParserResult<()>;
+
+    /// Copies a shared registry first, like `register_comparison`
     fn remove_comparison_by_symbol(&mut self, symbol: &str);
-    fn get_comparison(&self, symbol: &str) -> Option<Comparison>;
 
-    fn parse_to_node(&self, code: &str) -> ParserResult<Expr>;
-    fn parse_comparison(&self, value: Pair<Self::R>) -> ParserResult<Comparison>;
-    fn parse_constraint(&self, value: Pair<Self::R>) -> ParserResult<Constraint>;
-    fn parse_operator(&self, value: Pair<Self::R>) -> ParserResult<Operator>;
-    fn parse_expr(&self, value: Pair<Self::R>) -> ParserResult<Expr>;
+    fn parse_to_node(&self, code: &str) -> ParserResult<Expr> {
+        Ok(self.parse_borrowed(code)?.into_owned())
+    }
+
+    /// Same as `parse_to_node`, but the result borrows from `code` wherever possible
+    fn parse_borrowed<'i>(&self, code: &'i str) -> ParserResult<borrowed::Expr<'i>>;
+    /// Same as `parse_to_node`, but also returns the source positions of the parsed nodes
+    fn parse_with_spans(&self, code: &str) -> ParserResult<(Expr, SourceMap)>;
+    /// Parses as much of `code` as possible, collecting every problem instead of stopping at
+    /// the first one
+    fn parse_recovering(&self, code: &str) -> Recovered;
+}
+
+/// Parser of any `Dialect`, see `rsql::RsqlParser` and `fiql::FiqlParser`
+#[derive(Debug, Clone)]
+pub struct QueryParser<D> {
+    dialect: D,
+    comparisons: Arc<ComparisonRegistry>,
+    pub(crate) options: ParserOptions,
+}
+
+impl<D: Dialect + Default> Default for QueryParser<D> {
+    fn default() -> Self {
+        QueryParser::with_options(ParserOptions::default())
+    }
+}
+
+impl<D: Dialect + Default> QueryParser<D> {
+    pub fn with_options(options: ParserOptions) -> Self {
+        QueryParser::new(D::default(), options)
+    }
+}
+
+impl<D: Dialect> QueryParser<D> {
+    pub fn new(dialect: D, options: ParserOptions) -> Self {
+        QueryParser { dialect, comparisons: Arc::default(), options }
+    }
+
+    /// Replaces the registered comparisons by `registry`, after checking its symbols the way
+    /// `Parser::register_comparison` does
+    pub fn registry(mut self, registry: Arc<ComparisonRegistry>) -> ParserResult<Self> {
+        let mut checked: Vec<&Arc<Comparison>> = vec![];
+        for (_, comparison) in registry.iter() {
+            if checked.contains(&comparison) {
+                continue;
+            }
+            let symbols = registry.iter().filter(|(_, other)| *other == comparison);
+            self.check_symbols(symbols.map(|(symbol, _)| symbol), comparison)?;
+            checked.push(comparison);
+        }
+        self.comparisons = registry;
+        Ok(self)
+    }
+
+    pub fn dialect(&self) -> &D {
+        &self.dialect
+    }
+
+    pub fn options(&self) -> &ParserOptions {
+        &self.options
+    }
+
+    /// Checks the symbols of `comparison` against the options, the dialect and the default
+    /// comparisons. Symbols the dialect does not read are skipped if it reads another one, so
+    /// that one registry serves dialects with different syntaxes, e.g. `>` and `=gt=`
+    fn check_symbols<'s, I: IntoIterator<Item = &'s str>>(
+        &self, symbols: I, comparison: &Comparison,
+    ) -> ParserResult<()> {
+        let mut unread = None;
+        let mut read = false;
+        for symbol in symbols {
+            if !self.options.accepts_symbol(symbol) {
+                return Err(ParserError::InvalidComparison(symbol.to_string()));
+            }
+            if !self.dialect.reads_comparison(symbol, &self.options) {
+                unread = unread.or(Some(symbol));
+                continue;
+            }
+            read = true;
+            let default = Some(symbol)
+                .filter(|_| self.options.default_comparisons)
+                .and_then(|symbol| self.dialect.default_comparison(symbol));
+            if default.is_some_and(|known| *known != *comparison) {
+                return Err(ParserError::ConflictingComparison(symbol.to_string()));
+            }
+        }
+        match unread {
+            Some(symbol) if !read => Err(ParserError::AmbiguousComparison(symbol.to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    fn tokenize(&self, code: &str, recovering: bool) -> ParserResult<Vec<Token>> {
+        self.options.check_input(code)?;
+        let tokens = self.dialect.tokenize(code, &self.options, recovering)?;
+        let max = self.options.max_constraints;
+        let is_constraint =
+            |token: &&Token| matches!(token.kind, TokenKind::Constraint(_) | TokenKind::Exists);
+        if max != usize::MAX && tokens.iter().filter(is_constraint).count() > max {
+            return Err(ParserError::TooManyConstraints { max });
+        }
+        Ok(tokens)
+    }
+
+    /// Checks the tokens against the registered comparisons and the options, every error
+    /// becomes a part that is reported when building
+    fn parts<'i>(&self, code: &'i str, tokens: &[Token]) -> Vec<Part<'i>> {
+        let syntax_error = |offset: usize, expected: Vec<Expected>| {
+            ParserError::Syntax(SyntaxError::new(code, offset, expected))
+        };
+        let mut errors = vec![];
+        if !self.options.whitespace {
+            let ranges = tokens.iter().flat_map(|token| match &token.kind {
+                TokenKind::Constraint(constraint) => {
+                    let mut ranges =
+                        vec![constraint.selector.clone(), constraint.comparison.clone()];
+                    ranges.extend(constraint.values.iter().cloned());
+                    ranges
+                }
+                _ => vec![token.range.clone()],
+            });
+            let ranges = ranges.map(|range| (range.start, range.end));
+            for (start, end) in stray_whitespace(code, ranges) {
+                errors.push(Part {
+                    kind: PartKind::Error(syntax_error(start, vec![])),
+                    range: start..end,
+                });
+            }
+        }
+
+        let mut parts = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            let range = token.range.clone();
+            let text = &code[range.clone()];
+            let kind = match &token.kind {
+                TokenKind::Constraint(constraint) => {
+                    PartKind::Operand(self.constraint(code, constraint).map(borrowed::Expr::Item))
+                }
+                TokenKind::Exists => PartKind::Operand(
+                    self.dialect.decode_selector(text, &self.options).map(borrowed::Expr::Exists),
+                ),
+                TokenKind::Operator(op) => {
+                    // A rejected operator is reported, but still separates its operands
+                    if !self.options.accepts_operator(text) {
+                        let error = syntax_error(range.start, vec![Expected::LogicalOperator]);
+                        errors.push(Part { kind: PartKind::Error(error), range: range.clone() });
+                    }
+                    PartKind::Operator(op.clone())
+                }
+                TokenKind::Not if self.options.negation && self.options.accepts_operator(text) => {
+                    PartKind::Not
+                }
+                TokenKind::Not => PartKind::Error(syntax_error(
+                    range.start,
+                    vec![Expected::Selector, Expected::OpeningParenthesis],
+                )),
+                TokenKind::Open => PartKind::Open,
+                TokenKind::Close => PartKind::Close,
+                TokenKind::Invalid(error) => {
+                    PartKind::Operand(Err(ParserError::Syntax(error.clone())))
+                }
+            };
+            parts.push(Part { kind, range });
+        }
+        parts.extend(errors);
+        parts
+    }
+
+    fn constraint<'i>(
+        &self, code: &'i str, token: &ConstraintToken,
+    ) -> ParserResult<borrowed::Constraint<'i>> {
+        let selector =
+            self.dialect.decode_selector(&code[token.selector.clone()], &self.options)?;
+        let symbol = &code[token.comparison.clone()];
+        let comparison = Some(symbol)
+            .filter(|symbol| self.options.accepts_comparison(symbol))
+            .and_then(|symbol| self.get_comparison(symbol))
+            .ok_or_else(|| ParserError::InvalidComparison(symbol.to_string()))?;
+        let arguments = token
+            .values
+            .iter()
+            .map(|value| self.dialect.decode_value(&code[value.clone()], &self.options))
+            .collect::<ParserResult<Vec<_>>>()?;
+        self.options.check_arguments(&arguments)?;
+        comparison.arity().check(arguments.len())?;
+        let arguments = if self.options.typed_values {
+            arguments.into_iter().map(borrowed::Value::recognize).collect()
+        } else {
+            arguments
+        };
+        Ok(borrowed::Constraint { selector, comparison, arguments: borrowed::Arguments(arguments) })
+    }
+
+    fn build<'i>(&self, code: &'i str, tokens: &[Token]) -> ParserResult<borrowed::Expr<'i>> {
+        let parts = self.parts(code, tokens);
+        let (expr, diagnostics) = recovery::build(code, parts, self.options.max_depth);
+        match (expr, diagnostics.into_iter().next()) {
+            (_, Some(diagnostic)) => Err(diagnostic.error),
+            (Some(expr), None) => Ok(expr),
+            (None, None) => Err(ParserError::Syntax(SyntaxError::new(code, 0, vec![]))),
+        }
+    }
+}
+
+impl<D: Dialect> Parser for QueryParser<D> {
+    fn snapshot(&self) -> Arc<ComparisonRegistry> {
+        Arc::clone(&self.comparisons)
+    }
+
+    fn register_comparison(&mut self, comparison: &Comparison) -> ParserResult<()> {
+        self.check_symbols(comparison.get_symbols().iter().map(String::as_str), comparison)?;
+        Arc::make_mut(&mut self.comparisons).register(comparison)
+    }
+
+    fn remove_comparison_by_symbol(&mut self, symbol: &str) {
+        Arc::make_mut(&mut self.comparisons).remove_by_symbol(symbol);
+    }
+
+    fn get_comparison(&self, symbol: &str) -> Option<Arc<Comparison>> {
+        let registered = self.comparisons.get(symbol).cloned();
+        if self.options.default_comparisons {
+            registered.or_else(|| self.dialect.default_comparison(symbol))
+        } else {
+            registered
+        }
+    }
+
+    fn parse_borrowed<'i>(&self, code: &'i str) -> ParserResult<borrowed::Expr<'i>> {
+        let tokens = self.tokenize(code, false)?;
+        self.build(code, &tokens)
+    }
+
+    fn parse_with_spans(&self, code: &str) -> ParserResult<(Expr, SourceMap)> {
+        let tokens = self.tokenize(code, false)?;
+        let expr = self.build(code, &tokens)?.into_owned();
+
+        let index = LineIndex::new(code);
+        let span = |range: &std::ops::Range<usize>| index.span(range.start, range.end);
+        let mut source_map = SourceMap::default();
+        let mut open_groups = vec![];
+        for token in &tokens {
+            match &token.kind {
+                TokenKind::Constraint(constraint) => source_map.constraints.push(ConstraintSpan {
+                    span: span(&token.range),
+                    selector: span(&constraint.selector),
+                    comparison: span(&constraint.comparison),
+                    arguments: span(&constraint.arguments),
+                    values: constraint.values.iter().map(span).collect(),
+                }),
+                TokenKind::Operator(operator) => source_map
+                    .operators
+                    .push(OperatorSpan { operator: operator.clone(), span: span(&token.range) }),
+                TokenKind::Not => source_map.negations.push(span(&token.range)),
+                TokenKind::Exists => source_map.exists.push(span(&token.range)),
+                TokenKind::Open => open_groups.push(token.range.start),
+                TokenKind::Close => {
+                    if let Some(start) = open_groups.pop() {
+                        source_map.groups.push(span(&(start..token.range.end)));
+                    }
+                }
+                TokenKind::Invalid(_) => {}
+            }
+        }
+        source_map.groups.sort_by_key(|group: &Span| group.start.offset);
+        Ok((expr, source_map))
+    }
+
+    fn parse_recovering(&self, code: &str) -> Recovered {
+        match self.tokenize(code, true) {
+            Ok(tokens) => {
+                recovery::recover(code, self.parts(code, &tokens), self.options.max_depth)
+            }
+            Err(error) => Recovered {
+                expr: None,
+                diagnostics: vec![Diagnostic {
+                    span: LineIndex::new(code).span(0, code.len()),
+                    error,
+                }],
+            },
+        }
+    }
+}
+
+impl<D: Dialect + Default> TryFrom<&[Comparison]> for QueryParser<D> {
+    type Error = ParserError;
+
+    fn try_from(comparisons: &[Comparison]) -> ParserResult<Self> {
+        let mut parser = Self::default();
+        for comparison in comparisons {
+            parser.register_comparison(comparison)?;
+        }
+        Ok(parser)
+    }
+}
+
+impl<D: Dialect + Default> TryFrom<Vec<Comparison>> for QueryParser<D> {
+    type Error = ParserError;
+
+    fn try_from(comparisons: Vec<Comparison>) -> ParserResult<Self> {
+        comparisons.as_slice().try_into()
+    }
 }
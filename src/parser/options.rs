@@ -0,0 +1,372 @@
+use crate::borrowed::Value;
+use crate::error::ParserError;
+use crate::ParserResult;
+use regex::RegexSet;
+
+/// Switches for the parts of a dialect a parser accepts, everything but
+/// `case_insensitive_keywords` and `typed_values` is enabled by default.
+///
+/// Options that do not apply to a dialect, e.g. `quoted_values` for FIQL, are ignored by it.
+///
+/// The `max_*` limits protect against hostile queries, only `max_depth` is set by default.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParserOptions {
+    pub(crate) keyword_operators: bool,
+    pub(crate) case_insensitive_keywords: bool,
+    pub(crate) short_comparisons: bool,
+    pub(crate) whitespace: bool,
+    pub(crate) quoted_values: bool,
+    pub(crate) default_comparisons: bool,
+    pub(crate) negation: bool,
+    pub(crate) percent_decoding: bool,
+    pub(crate) typed_values: bool,
+    pub(crate) max_length: usize,
+    pub(crate) max_depth: usize,
+    pub(crate) max_constraints: usize,
+    pub(crate) max_arguments: usize,
+    pub(crate) max_argument_length: usize,
+    pub(crate) symbol_patterns: SymbolPatterns,
+}
+
+/// Default of `ParserOptions::max_depth`
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// Default of `ParserOptions::symbol_patterns`: `=word=`, `<`, `<=`, `>`, `>=` and `!=`
+pub const DEFAULT_SYMBOL_PATTERNS: &[&str] = &[r"^=[a-zA-Z]*=$", r"^[<>]=?$", r"^!=$"];
+
+/// Symbols switched off by `short_comparisons(false)`
+const SHORT_COMPARISONS: &[&str] = &["<", "<=", ">", ">="];
+
+lazy_static! {
+    static ref DEFAULT_SYMBOL_SET: RegexSet = RegexSet::new(DEFAULT_SYMBOL_PATTERNS).unwrap();
+}
+
+/// A `RegexSet` compared by its patterns
+#[derive(Debug, Clone)]
+pub(crate) struct SymbolPatterns(RegexSet);
+
+impl PartialEq for SymbolPatterns {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.patterns() == other.0.patterns()
+    }
+}
+
+impl Eq for SymbolPatterns {}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        ParserOptions {
+            keyword_operators: true,
+            case_insensitive_keywords: false,
+            short_comparisons: true,
+            whitespace: true,
+            quoted_values: true,
+            default_comparisons: true,
+            negation: true,
+            percent_decoding: true,
+            typed_values: false,
+            max_length: usize::MAX,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_constraints: usize::MAX,
+            max_arguments: usize::MAX,
+            max_argument_length: usize::MAX,
+            symbol_patterns: SymbolPatterns(DEFAULT_SYMBOL_SET.clone()),
+        }
+    }
+}
+
+impl ParserOptions {
+    /// `and`, `or` and `not` besides `;`, `,` and `!` in RSQL
+    pub fn keyword_operators(mut self, enabled: bool) -> Self {
+        self.keyword_operators = enabled;
+        self
+    }
+
+    /// `AND`, `Or`... besides the lowercase keywords
+    pub fn case_insensitive_keywords(mut self, enabled: bool) -> Self {
+        self.case_insensitive_keywords = enabled;
+        self
+    }
+
+    /// `<`, `<=`, `>` and `>=` besides `=lt=`, `=le=`, `=gt=` and `=ge=`
+    pub fn short_comparisons(mut self, enabled: bool) -> Self {
+        self.short_comparisons = enabled;
+        self
+    }
+
+    /// Spaces, tabs and line breaks between the tokens of RSQL, keyword operators need it
+    pub fn whitespace(mut self, enabled: bool) -> Self {
+        self.whitespace = enabled;
+        self
+    }
+
+    /// Single and double quoted arguments in RSQL
+    pub fn quoted_values(mut self, enabled: bool) -> Self {
+        self.quoted_values = enabled;
+        self
+    }
+
+    /// The built-in comparisons of the dialect, when disabled only the registered ones are
+    /// accepted
+    pub fn default_comparisons(mut self, enabled: bool) -> Self {
+        self.default_comparisons = enabled;
+        self
+    }
+
+    /// `!`/`not` prefixes in RSQL, when disabled only standard RSQL is accepted
+    pub fn negation(mut self, enabled: bool) -> Self {
+        self.negation = enabled;
+        self
+    }
+
+    /// Decoding of `%XX` sequences in FIQL selectors and arguments
+    pub fn percent_decoding(mut self, enabled: bool) -> Self {
+        self.percent_decoding = enabled;
+        self
+    }
+
+    /// Recognition of `null`, `true`, `false`, integers and floats in unquoted arguments, see
+    /// `Value`
+    pub fn typed_values(mut self, enabled: bool) -> Self {
+        self.typed_values = enabled;
+        self
+    }
+
+    /// Maximum length of a query in bytes
+    pub fn max_length(mut self, max: usize) -> Self {
+        self.max_length = max;
+        self
+    }
+
+    /// Maximum number of nested groups, and of negations in a row
+    pub fn max_depth(mut self, max: usize) -> Self {
+        self.max_depth = max;
+        self
+    }
+
+    /// Maximum number of constraints in a query
+    pub fn max_constraints(mut self, max: usize) -> Self {
+        self.max_constraints = max;
+        self
+    }
+
+    /// Maximum number of arguments of a constraint
+    pub fn max_arguments(mut self, max: usize) -> Self {
+        self.max_arguments = max;
+        self
+    }
+
+    /// Maximum length of an argument in bytes, after unescaping and percent-decoding
+    pub fn max_argument_length(mut self, max: usize) -> Self {
+        self.max_argument_length = max;
+        self
+    }
+
+    /// Patterns the symbols of registered comparisons must match, `DEFAULT_SYMBOL_PATTERNS` by
+    /// default. The built-in dialects read `=word=` with digits and runs of two or more of
+    /// `=!~<>@` besides their own comparisons, e.g. `=in2=`, `=~` or `=@`
+    pub fn symbol_patterns(mut self, patterns: RegexSet) -> Self {
+        self.symbol_patterns = SymbolPatterns(patterns);
+        self
+    }
+
+    pub(crate) fn check_input(&self, code: &str) -> ParserResult<()> {
+        if code.len() > self.max_length {
+            return Err(ParserError::InputTooLong { max: self.max_length, length: code.len() });
+        }
+        Ok(())
+    }
+
+    /// Checks `max_depth` by scanning for parentheses, which is much cheaper than tokenizing
+    /// hostile input first. `quotes` tells whether `'` and `"` start a quoted value
+    pub(crate) fn check_depth(&self, code: &str, quotes: bool) -> ParserResult<()> {
+        let max = self.max_depth;
+        match scan_groups(code, quotes, |depth| depth > max) {
+            Some(offset) => Err(ParserError::NestingTooDeep { max, offset }),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_arguments(&self, arguments: &[Value<'_>]) -> ParserResult<()> {
+        if arguments.len() > self.max_arguments {
+            return Err(ParserError::TooManyArguments {
+                max: self.max_arguments,
+                count: arguments.len(),
+            });
+        }
+        let mut lengths = arguments.iter().filter_map(|arg| arg.as_str()).map(str::len);
+        match lengths.find(|&length| length > self.max_argument_length) {
+            Some(length) => {
+                Err(ParserError::ArgumentTooLong { max: self.max_argument_length, length })
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the logical operator or negation `op`, as written in the query, is accepted
+    pub(crate) fn accepts_operator(&self, op: &str) -> bool {
+        if !op.chars().any(|c| c.is_ascii_alphabetic()) {
+            return true;
+        }
+        self.keyword_operators
+            && (self.case_insensitive_keywords || op.chars().all(|c| c.is_ascii_lowercase()))
+    }
+
+    pub(crate) fn accepts_symbol(&self, symbol: &str) -> bool {
+        self.symbol_patterns.0.is_match(symbol)
+    }
+
+    /// Registered symbols starting with `<` or `>`, e.g. `<~`, are not short comparisons
+    pub(crate) fn accepts_comparison(&self, symbol: &str) -> bool {
+        self.short_comparisons || !SHORT_COMPARISONS.contains(&symbol)
+    }
+}
+
+/// Whitespace between the tokens of RSQL
+fn is_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\r' | '\n')
+}
+
+/// Chars the built-in grammars read comparisons from, e.g. `=in=`, `<=`, `!=` or `=~`
+fn is_symbol_char(c: char) -> bool {
+    matches!(c, '=' | '!' | '~' | '<' | '>' | '@')
+}
+
+/// Number of groups `code` leaves open, counted the way `check_depth` does
+pub(crate) fn open_groups(code: &str, quotes: bool) -> usize {
+    let mut open = 0;
+    scan_groups(code, quotes, |depth| {
+        open = depth;
+        false
+    });
+    open
+}
+
+/// Walks the group parentheses of `code`, skipping argument lists and quoted values, and calls
+/// `stop` with the depth after each of them. Returns the offset of the first parenthesis `stop`
+/// accepted
+fn scan_groups<F: FnMut(usize) -> bool>(code: &str, quotes: bool, mut stop: F) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_arguments = false;
+    let mut quote = None;
+    // Whether the last chars besides whitespace are symbol chars, and if so whether they are a
+    // comparison rather than `!` negations
+    let mut symbols: Option<bool> = None;
+    let mut chars = code.char_indices();
+    while let Some((offset, c)) = chars.next() {
+        match (quote, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') if quotes => quote = Some(c),
+            // A parenthesis right after a comparison opens an argument list, not a group
+            (None, '(') if symbols == Some(true) => in_arguments = true,
+            (None, '(') => {
+                depth += 1;
+                if stop(depth) {
+                    return Some(offset);
+                }
+            }
+            (None, ')') if in_arguments => in_arguments = false,
+            (None, ')') => {
+                depth = depth.saturating_sub(1);
+                if stop(depth) {
+                    return Some(offset);
+                }
+            }
+            _ => {}
+        }
+        if quote.is_some() || !is_symbol_char(c) && !is_space(c) {
+            symbols = None;
+        } else if is_symbol_char(c) {
+            symbols = Some(symbols == Some(true) || c != '!');
+        }
+    }
+    None
+}
+
+/// Ranges of the whitespace runs of `code` outside of `tokens`, which are the sorted
+/// ranges of the tokens whitespace is allowed in
+pub(crate) fn stray_whitespace<I: IntoIterator<Item = (usize, usize)>>(
+    code: &str, tokens: I,
+) -> Vec<(usize, usize)> {
+    let mut runs = vec![];
+    let mut scan = |from: usize, to: usize| {
+        let mut start = None;
+        for (idx, c) in code[from..to].char_indices() {
+            match (is_space(c), start) {
+                (true, None) => start = Some(from + idx),
+                (false, Some(run_start)) => {
+                    runs.push((run_start, from + idx));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(run_start) = start {
+            runs.push((run_start, to));
+        }
+    };
+    let mut pos = 0;
+    for (start, end) in tokens {
+        if start > pos {
+            scan(pos, start);
+        }
+        pos = pos.max(end);
+    }
+    scan(pos, code.len());
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ParserError;
+    use crate::parser::options::{open_groups, stray_whitespace, ParserOptions};
+
+    #[test]
+    fn test_accepts_operator() {
+        let options = ParserOptions::default();
+        assert!(options.accepts_operator(";"));
+        assert!(options.accepts_operator("and"));
+        assert!(!options.accepts_operator("AND"));
+        assert!(options.clone().case_insensitive_keywords(true).accepts_operator("Or"));
+        assert!(!options.clone().keyword_operators(false).accepts_operator("not"));
+        assert!(options.keyword_operators(false).accepts_operator("!"));
+    }
+
+    #[test]
+    fn test_stray_whitespace() {
+        let code = "a==' b' ;\tc==1 ";
+        assert_eq!(
+            stray_whitespace(code, vec![(0, 1), (1, 3), (3, 7), (8, 9)]),
+            [(7, 8), (9, 10), (14, 15)]
+        );
+        assert!(stray_whitespace("a==1", vec![]).is_empty());
+    }
+
+    #[test]
+    fn test_check_input() {
+        let options = ParserOptions::default().max_depth(2);
+        assert!(options.check_depth("((a=in=(1,2));b==1),(c==')))((((')", true).is_ok());
+        assert!(matches!(
+            options.check_depth("((a==1;(b==2)))", true),
+            Err(ParserError::NestingTooDeep { max: 2, offset: 7 })
+        ));
+        assert!(matches!(
+            options.check_depth("((a=='x';(b==2)))", false),
+            Err(ParserError::NestingTooDeep { max: 2, offset: 9 })
+        ));
+        assert_eq!(open_groups("((a=in=(1,2));(b=='(", true), 2);
+        assert_eq!(open_groups("(a=~(x,y);b!~(x);c=@(x);!(d>(1)", true), 2);
+        assert!(matches!(
+            ParserOptions::default().max_depth(0).check_depth("a==1;!!(b==2)", true),
+            Err(ParserError::NestingTooDeep { max: 0, offset: 7 })
+        ));
+        assert!(matches!(
+            options.max_length(3).check_input("a==1"),
+            Err(ParserError::InputTooLong { max: 3, length: 4 })
+        ));
+    }
+}
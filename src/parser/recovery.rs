@@ -0,0 +1,249 @@
+use crate::borrowed;
+use crate::error::{Expected, ParserError, SyntaxError};
+use crate::span::{LineIndex, Span};
+use crate::{Expr, Operator, ParserResult};
+use std::ops::Range;
+
+/// A problem found by `Parser::parse_recovering`
+#[derive(Debug)]
+pub struct Diagnostic {
+    /// The part of the input the problem refers to
+    pub span: Span,
+    pub error: ParserError,
+}
+
+/// The result of `Parser::parse_recovering`
+#[derive(Debug)]
+pub struct Recovered {
+    /// Everything that could be parsed, `None` if nothing could
+    pub expr: Option<Expr>,
+    /// Every problem found, in source order
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Recovered {
+    pub fn is_ok(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+pub(crate) enum PartKind<'i> {
+    /// A constraint, or the error found when parsing it
+    Operand(ParserResult<borrowed::Expr<'i>>),
+    Operator(Operator),
+    Not,
+    Open,
+    Close,
+    /// A part that is reported and otherwise skipped
+    Error(ParserError),
+}
+
+pub(crate) struct Part<'i> {
+    pub(crate) kind: PartKind<'i>,
+    /// Byte range in the input, only turned into a `Span` when the part is reported
+    pub(crate) range: Range<usize>,
+}
+
+struct Group<'i> {
+    open: Option<Range<usize>>,
+    /// Negations in front of the group
+    negated: usize,
+    /// Negations waiting for the next operand
+    negations: usize,
+    or_operands: Vec<borrowed::Expr<'i>>,
+    and_operands: Vec<borrowed::Expr<'i>>,
+    expect_operand: bool,
+}
+
+impl<'i> Group<'i> {
+    fn new(open: Option<Range<usize>>, negated: usize) -> Self {
+        Group {
+            open,
+            negated,
+            negations: 0,
+            or_operands: vec![],
+            and_operands: vec![],
+            expect_operand: true,
+        }
+    }
+
+    fn push_operand(&mut self, expr: borrowed::Expr<'i>) {
+        self.and_operands.push(expr.negated(self.negations));
+        self.negations = 0;
+    }
+
+    fn push_or(&mut self) {
+        if let Some(expr) = borrowed::Expr::join(Operator::And, self.and_operands.drain(..)) {
+            self.or_operands.push(expr);
+        }
+    }
+
+    fn finish(mut self) -> Option<borrowed::Expr<'i>> {
+        self.push_or();
+        let negated = self.negated;
+        borrowed::Expr::join(Operator::Or, self.or_operands).map(|expr| expr.negated(negated))
+    }
+}
+
+/// Same as `build`, with the result converted into a `Recovered`
+pub(crate) fn recover<'i, I: IntoIterator<Item = Part<'i>>>(
+    code: &str, parts: I, max_depth: usize,
+) -> Recovered {
+    let (expr, diagnostics) = build(code, parts, max_depth);
+    Recovered { expr: expr.map(borrowed::Expr::into_owned), diagnostics }
+}
+
+/// Builds an expression from a flat stream of parts, matching its parentheses with an explicit
+/// stack. Every misplaced or broken part is reported instead of stopping at the first one
+pub(crate) fn build<'i, I: IntoIterator<Item = Part<'i>>>(
+    code: &str, parts: I, max_depth: usize,
+) -> (Option<borrowed::Expr<'i>>, Vec<Diagnostic>) {
+    let index = LineIndex::new(code);
+    let mut diagnostics = vec![];
+    let report = |range: &Range<usize>, error: ParserError| Diagnostic {
+        span: index.span(range.start, range.end),
+        error,
+    };
+    let syntax_error = |range: &Range<usize>, offset: usize, expected: Vec<Expected>| {
+        report(range, ParserError::Syntax(SyntaxError::new(code, offset, expected)))
+    };
+
+    let mut groups = vec![Group::new(None, 0)];
+    let mut too_deep = 0;
+    for part in parts {
+        let range = &part.range;
+        let top = groups.len() - 1;
+        let after_operand =
+            if top == 0 { Expected::EndOfInput } else { Expected::ClosingParenthesis };
+        let group = &mut groups[top];
+        match part.kind {
+            PartKind::Operand(operand) => {
+                if !group.expect_operand {
+                    diagnostics.push(syntax_error(
+                        range,
+                        range.start,
+                        vec![Expected::LogicalOperator, after_operand],
+                    ));
+                }
+                group.expect_operand = false;
+                match operand {
+                    Ok(expr) => group.push_operand(expr),
+                    Err(error) => {
+                        group.negations = 0;
+                        diagnostics.push(report(range, error))
+                    }
+                }
+            }
+            PartKind::Operator(op) => {
+                if group.expect_operand {
+                    diagnostics.push(syntax_error(
+                        range,
+                        range.start,
+                        vec![Expected::Selector, Expected::OpeningParenthesis],
+                    ));
+                }
+                group.expect_operand = true;
+                group.negations = 0;
+                if op == Operator::Or {
+                    group.push_or();
+                }
+            }
+            PartKind::Not => {
+                if !group.expect_operand {
+                    diagnostics.push(syntax_error(
+                        range,
+                        range.start,
+                        vec![Expected::LogicalOperator, after_operand],
+                    ));
+                    group.expect_operand = true;
+                }
+                if group.negations == max_depth {
+                    let error = ParserError::NestingTooDeep { max: max_depth, offset: range.start };
+                    diagnostics.push(report(range, error));
+                } else {
+                    group.negations += 1;
+                }
+            }
+            PartKind::Open => {
+                if !group.expect_operand {
+                    diagnostics.push(syntax_error(
+                        range,
+                        range.start,
+                        vec![Expected::LogicalOperator, after_operand],
+                    ));
+                }
+                // Groups beyond `max_depth` are dropped, their contents join the enclosing group
+                if top >= max_depth {
+                    if too_deep == 0 {
+                        let error =
+                            ParserError::NestingTooDeep { max: max_depth, offset: range.start };
+                        diagnostics.push(report(range, error));
+                    }
+                    too_deep += 1;
+                    continue;
+                }
+                let negated = std::mem::take(&mut group.negations);
+                groups.push(Group::new(Some(part.range), negated));
+            }
+            PartKind::Close if too_deep > 0 => {
+                too_deep -= 1;
+                if group.expect_operand {
+                    diagnostics.push(syntax_error(
+                        range,
+                        range.start,
+                        vec![Expected::Selector, Expected::OpeningParenthesis],
+                    ));
+                }
+            }
+            PartKind::Close => {
+                if group.expect_operand {
+                    diagnostics.push(syntax_error(
+                        range,
+                        range.start,
+                        vec![Expected::Selector, Expected::OpeningParenthesis],
+                    ));
+                }
+                if top == 0 {
+                    if !group.expect_operand {
+                        diagnostics.push(syntax_error(
+                            range,
+                            range.start,
+                            vec![Expected::LogicalOperator, Expected::EndOfInput],
+                        ));
+                    }
+                    continue;
+                }
+                let expr = groups.pop().and_then(Group::finish);
+                let parent = &mut groups[top - 1];
+                parent.expect_operand = false;
+                parent.and_operands.extend(expr);
+            }
+            PartKind::Error(error) => diagnostics.push(report(range, error)),
+        }
+    }
+
+    if groups.last().is_some_and(|group| group.expect_operand) {
+        diagnostics.push(syntax_error(
+            &(code.len()..code.len()),
+            code.len(),
+            vec![Expected::Selector, Expected::OpeningParenthesis],
+        ));
+    }
+    while groups.len() > 1 {
+        let group = groups.pop().unwrap();
+        if let Some(open) = &group.open {
+            diagnostics.push(syntax_error(
+                open,
+                code.len(),
+                vec![Expected::LogicalOperator, Expected::ClosingParenthesis],
+            ));
+        }
+        let expr = group.finish();
+        let parent = groups.last_mut().unwrap();
+        parent.expect_operand = false;
+        parent.and_operands.extend(expr);
+    }
+
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start.offset);
+    (groups.pop().and_then(Group::finish), diagnostics)
+}
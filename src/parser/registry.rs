@@ -0,0 +1,98 @@
+//! Comparisons registered once and shared between parsers.
+//!
+//! A `ComparisonRegistry` is built with `register`, frozen into an `Arc` and handed to any
+//! number of parsers with `QueryParser::registry`, across threads as well. `Parser::snapshot`
+//! returns the registry of a parser without copying it, a reconfigured registry is built from a
+//! clone of a snapshot and swapped in.
+
+use crate::error::ParserError;
+use crate::Comparison;
+use crate::ParserResult;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Every comparison is stored once and shared by its symbols and the parsed constraints
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct ComparisonRegistry {
+    comparisons: HashMap<String, Arc<Comparison>>,
+}
+
+impl ComparisonRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `comparison` under all of its symbols, unless one of them already stands for
+    /// another comparison. Which symbols a parser reads is checked when the registry is handed
+    /// to it
+    pub fn register(&mut self, comparison: &Comparison) -> ParserResult<()> {
+        let symbols = comparison.get_symbols();
+        if let Some(symbol) = symbols.iter().find(|symbol| {
+            self.comparisons.get(symbol.as_str()).is_some_and(|known| **known != *comparison)
+        }) {
+            return Err(ParserError::ConflictingComparison(symbol.clone()));
+        }
+        let comparison = Arc::new(comparison.clone());
+        for symbol in symbols {
+            self.comparisons.insert(symbol.clone(), Arc::clone(&comparison));
+        }
+        Ok(())
+    }
+
+    /// Registers the comparisons, see `register`
+    pub fn with(mut self, comparisons: &[Comparison]) -> ParserResult<Self> {
+        for comparison in comparisons {
+            self.register(comparison)?;
+        }
+        Ok(self)
+    }
+
+    pub fn remove_by_symbol(&mut self, symbol: &str) -> Option<Arc<Comparison>> {
+        self.comparisons.remove(symbol)
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&Arc<Comparison>> {
+        self.comparisons.get(symbol)
+    }
+
+    /// The registered symbols and their comparisons, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Arc<Comparison>)> {
+        self.comparisons.iter().map(|(symbol, comparison)| (symbol.as_str(), comparison))
+    }
+
+    pub fn len(&self) -> usize {
+        self.comparisons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.comparisons.is_empty()
+    }
+
+    /// Freezes the registry so that it can be shared
+    pub fn freeze(self) -> Arc<Self> {
+        Arc::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ParserError;
+    use crate::parser::registry::ComparisonRegistry;
+    use crate::{Arity, Comparison};
+
+    #[test]
+    fn test_register() -> anyhow::Result<()> {
+        let mut registry = ComparisonRegistry::new().with(&[Comparison::IN()])?;
+        registry.register(&Comparison::IN())?;
+        let conflicting = Comparison::new(&["=any=", "=in="], Arity::Exact(1))?;
+        assert!(matches!(
+            registry.register(&conflicting),
+            Err(ParserError::ConflictingComparison(ref symbol)) if symbol == "=in="
+        ));
+        assert!(registry.get("=any=").is_none());
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.remove_by_symbol("=in=").as_deref(), Some(&Comparison::IN()));
+        assert!(registry.is_empty());
+        Ok(())
+    }
+}
@@ -1,71 +1,173 @@
-use crate::Arguments;
+use crate::ast::comparison::shared;
+use crate::borrowed::Value;
+use crate::error::{Expected, ParserError, SyntaxError};
+use crate::parser::dialect::{Dialect, Token, TokenKind};
+use crate::parser::grammar::{self, Grammar, Role};
+use crate::parser::options::ParserOptions;
+use crate::parser::QueryParser;
 use crate::Comparison;
+use grammar_impl::{RsqlGrammar, Rule};
+use std::borrow::Cow;
+use std::sync::Arc;
 
-use crate::error::ParserError;
-use crate::parser::Parser;
-use pest::iterators::Pair;
-use pest::Parser as PestParser;
-use std::collections::HashMap;
-use std::convert::{TryFrom, TryInto};
+mod grammar_impl {
+    #[derive(Parser)]
+    #[grammar = "rsql.pest"]
+    pub struct RsqlGrammar;
+}
 
-#[derive(Parser, Default)]
-#[grammar = "rsql.pest"]
-pub struct RsqlParser(HashMap<String, Comparison>);
-gen_parser!(RsqlParser);
+/// RSQL: FIQL with quoted values, `<`/`>` comparisons, whitespace, keyword operators and
+/// negation
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Rsql;
 
-impl Parser for RsqlParser {
-    type R = Rule;
+pub type RsqlParser = QueryParser<Rsql>;
 
-    gen_basic_parser!(RSQL);
+impl Dialect for Rsql {
+    fn tokenize(
+        &self, code: &str, options: &ParserOptions, recovering: bool,
+    ) -> Result<Vec<Token>, ParserError> {
+        let mut tokens = grammar::tokenize::<Rule, RsqlGrammar>(code, options, recovering)?;
+        if !options.quoted_values {
+            for token in &mut tokens {
+                let quoted = match &token.kind {
+                    TokenKind::Constraint(constraint) => constraint
+                        .values
+                        .iter()
+                        .find(|value| code[value.start..value.end].starts_with(['"', '\''])),
+                    _ => None,
+                };
+                if let Some(quoted) = quoted {
+                    let error = SyntaxError::new(code, quoted.start, vec![Expected::Argument]);
+                    token.kind = TokenKind::Invalid(error);
+                }
+            }
+        }
+        Ok(tokens)
+    }
+
+    fn default_comparison(&self, symbol: &str) -> Option<Arc<Comparison>> {
+        let comparison: &Arc<Comparison> = match symbol {
+            "==" => &shared::EQUAL,
+            "!=" => &shared::NOT_EQUAL,
+            ">" | "=gt=" => &shared::GREATER_THAN,
+            ">=" | "=ge=" => &shared::GREATER_THAN_OR_EQUAL,
+            "<" | "=lt=" => &shared::LESS_THAN,
+            "<=" | "=le=" => &shared::LESS_THAN_OR_EQUAL,
+            "=in=" => &shared::IN,
+            "=out=" => &shared::OUT,
+            _ => return None,
+        };
+        Some(Arc::clone(comparison))
+    }
+
+    fn decode_value<'i>(
+        &self, value: &'i str, _options: &ParserOptions,
+    ) -> Result<Value<'i>, ParserError> {
+        if value.starts_with(['"', '\'']) {
+            Ok(Value::String(unescape(&value[1..value.len() - 1])))
+        } else {
+            Ok(Value::Unquoted(Cow::Borrowed(value)))
+        }
+    }
 }
 
-impl<'i> TryFrom<Pair<'i, Rule>> for Arguments {
-    type Error = ParserError;
+impl Grammar<Rule> for RsqlGrammar {
+    const QUERY: Rule = Rule::query;
+    const RECOVERY: Rule = Rule::recovery;
+    const SINGLE_CONSTRAINT: Rule = Rule::single_constraint;
+    const QUOTES: bool = true;
 
-    fn try_from(value: Pair<'i, Rule>) -> Result<Self, Self::Error> {
-        match value.as_rule() {
-            Rule::argument => {
-                let mut args = vec![];
-                for arg_item in value.into_inner() {
-                    let item_rules = arg_item.as_rule();
-                    let args_inner = arg_item.into_inner();
-                    match item_rules {
-                        Rule::value => {
-                            for arg_inner in args_inner {
-                                match arg_inner.as_rule() {
-                                    Rule::unreserved_str => {
-                                        for unreserved_inner in arg_inner.into_inner() {
-                                            if unreserved_inner.as_rule() == Rule::unreserved_inner
-                                            {
-                                                args.push(unreserved_inner.as_str().to_string());
-                                            }
-                                        }
-                                    }
-                                    Rule::double_quoted => {
-                                        for double_inner in arg_inner.into_inner() {
-                                            if double_inner.as_rule() == Rule::double_quoted_inner {
-                                                args.push(double_inner.as_str().to_string());
-                                            }
-                                        }
-                                    }
-                                    Rule::single_quoted => {
-                                        for single_inner in arg_inner.into_inner() {
-                                            if single_inner.as_rule() == Rule::single_quoted_inner {
-                                                args.push(single_inner.as_str().to_string());
-                                            }
-                                        }
-                                    }
-                                    _ => ParserError::invalid_pair_rule()?,
-                                }
-                            }
-                        }
-                        _ => ParserError::invalid_pair_rule()?,
-                    }
-                }
+    fn role(rule: Rule) -> Role {
+        match rule {
+            Rule::expression => Role::Expression,
+            Rule::constraint => Role::Constraint,
+            Rule::selector => Role::Selector,
+            Rule::comparison => Role::Comparison,
+            Rule::argument => Role::Arguments,
+            Rule::value => Role::Value,
+            Rule::operator => Role::Operator,
+            Rule::and_op => Role::And,
+            Rule::not_op => Role::Not,
+            Rule::open_paren => Role::Open,
+            Rule::close_paren => Role::Close,
+            Rule::invalid => Role::Invalid,
+            _ => Role::Other,
+        }
+    }
+
+    fn expected(rule: Rule) -> &'static [Expected] {
+        expected(rule)
+    }
+}
 
-                Ok(Arguments(args))
+/// Resolves the escape sequences of a quoted argument, lone surrogates of `\uXXXX` become
+/// U+FFFD
+fn unescape(quoted: &str) -> Cow<'_, str> {
+    if !quoted.contains('\\') {
+        return Cow::Borrowed(quoted);
+    }
+    let mut unescaped = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('b') => unescaped.push('\u{8}'),
+            Some('f') => unescaped.push('\u{c}'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('t') => unescaped.push('\t'),
+            Some('u') => {
+                let high = hex_code(&mut chars);
+                let code = if (0xD800..0xDC00).contains(&high) && chars.as_str().starts_with("\\u")
+                {
+                    let mut lookahead = chars.clone();
+                    lookahead.nth(1);
+                    let low = hex_code(&mut lookahead);
+                    if (0xDC00..0xE000).contains(&low) {
+                        chars = lookahead;
+                        0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                    } else {
+                        high
+                    }
+                } else {
+                    high
+                };
+                unescaped.push(std::char::from_u32(code).unwrap_or('\u{FFFD}'));
             }
-            _ => ParserError::invalid_pair_rule()?,
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    Cow::Owned(unescaped)
+}
+
+fn hex_code(chars: &mut std::str::Chars<'_>) -> u32 {
+    chars.take(4).filter_map(|c| c.to_digit(16)).fold(0, |code, digit| code * 16 + digit)
+}
+
+fn expected(rule: Rule) -> &'static [Expected] {
+    match rule {
+        Rule::expression => &[Expected::Selector, Expected::OpeningParenthesis],
+        Rule::constraint | Rule::selector => &[Expected::Selector],
+        Rule::open_paren => &[Expected::OpeningParenthesis],
+        Rule::close_paren => &[Expected::ClosingParenthesis],
+        Rule::operator | Rule::and_op | Rule::or_op => &[Expected::LogicalOperator],
+        Rule::comparison | Rule::comp_fiql | Rule::comp_alt | Rule::comp_symbol => {
+            &[Expected::ComparisonOperator]
         }
+        Rule::argument
+        | Rule::value
+        | Rule::unreserved_str
+        | Rule::unreserved_inner
+        | Rule::double_quoted
+        | Rule::double_quoted_inner
+        | Rule::single_quoted
+        | Rule::single_quoted_inner => &[Expected::Argument],
+        Rule::EOI => &[Expected::EndOfInput],
+        _ => &[],
     }
 }
@@ -0,0 +1,119 @@
+use crate::Operator;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// A location in the parsed input, `line` and `column` are 1-based and `column` counts chars
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Hash)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A byte range in the parsed input, together with its line/column bounds
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Hash)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn range(&self) -> Range<usize> {
+        self.start.offset..self.end.offset
+    }
+
+    /// The part of `input` covered by this span, `input` should be the parsed code
+    pub fn as_str<'a>(&self, input: &'a str) -> &'a str {
+        &input[self.range()]
+    }
+}
+
+/// Spans of a constraint and its components
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct ConstraintSpan {
+    pub span: Span,
+    pub selector: Span,
+    pub comparison: Span,
+    pub arguments: Span,
+    /// One span for every argument value, quotes included
+    pub values: Vec<Span>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct OperatorSpan {
+    pub operator: Operator,
+    pub span: Span,
+}
+
+/// Side table of the source positions of a parsed `Expr`.
+///
+/// Every list is in source order, so `constraints[i]` belongs to the i-th item of
+/// `Expr::constraints()`.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Default)]
+pub struct SourceMap {
+    pub constraints: Vec<ConstraintSpan>,
+    pub operators: Vec<OperatorSpan>,
+    /// Spans of the parenthesized groups, parentheses included
+    pub groups: Vec<Span>,
+    /// Spans of the `!`/`not` prefixes
+    pub negations: Vec<Span>,
+    /// Spans of the bare selectors of `Expr::Exists`
+    pub exists: Vec<Span>,
+}
+
+/// Maps byte offsets into line/column positions
+pub(crate) struct LineIndex<'i> {
+    input: &'i str,
+    line_starts: Vec<usize>,
+    /// Whether a line is ASCII only, so that its columns are byte offsets
+    ascii_lines: Vec<bool>,
+}
+
+impl<'i> LineIndex<'i> {
+    pub(crate) fn new(input: &'i str) -> Self {
+        let line_starts: Vec<usize> =
+            std::iter::once(0).chain(input.match_indices('\n').map(|(idx, _)| idx + 1)).collect();
+        let ascii_lines = line_starts
+            .iter()
+            .zip(line_starts.iter().skip(1).chain(std::iter::once(&input.len())))
+            .map(|(&start, &end)| input.as_bytes()[start..end].is_ascii())
+            .collect();
+        LineIndex { input, line_starts, ascii_lines }
+    }
+
+    pub(crate) fn position(&self, offset: usize) -> Position {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+        let line_start = self.line_starts[line];
+        let column = if self.ascii_lines[line] {
+            offset - line_start + 1
+        } else {
+            self.input[line_start..offset].chars().count() + 1
+        };
+        Position { offset, line: line + 1, column }
+    }
+
+    pub(crate) fn span(&self, start: usize, end: usize) -> Span {
+        Span { start: self.position(start), end: self.position(end) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::span::{LineIndex, Position};
+
+    #[test]
+    fn test_position() {
+        let index = LineIndex::new("a==1;\nb==\"ü\"\n;c==3");
+        assert_eq!(index.position(0), Position { offset: 0, line: 1, column: 1 });
+        assert_eq!(index.position(5), Position { offset: 5, line: 1, column: 6 });
+        assert_eq!(index.position(6), Position { offset: 6, line: 2, column: 1 });
+        assert_eq!(index.position(12), Position { offset: 12, line: 2, column: 6 });
+        assert_eq!(index.position(14), Position { offset: 14, line: 3, column: 1 });
+
+        let span = index.span(6, 13);
+        assert_eq!(span.as_str("a==1;\nb==\"ü\"\n;c==3"), "b==\"ü\"");
+    }
+}
@@ -0,0 +1,104 @@
+use rsql::error::{Expected, ParserError, SyntaxError};
+use rsql::parser::dialect::{ConstraintToken, Dialect, Token, TokenKind};
+use rsql::parser::options::ParserOptions;
+use rsql::parser::{Parser, QueryParser};
+use rsql::{Comparison, Expr, Operator};
+use std::sync::Arc;
+
+/// A C-like dialect without any grammar: `a==1 && !(b!=2 || c==3)`. Its comparisons are built
+/// once and shared by the parsed constraints
+struct Logic {
+    equal: Arc<Comparison>,
+    not_equal: Arc<Comparison>,
+}
+
+impl Default for Logic {
+    fn default() -> Self {
+        Logic { equal: Arc::new(Comparison::EQUAL()), not_equal: Arc::new(Comparison::NOT_EQUAL()) }
+    }
+}
+
+impl Dialect for Logic {
+    fn tokenize(
+        &self, code: &str, _options: &ParserOptions, _recovering: bool,
+    ) -> Result<Vec<Token>, ParserError> {
+        let word = |s: &str| s.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(s.len());
+        let mut tokens = vec![];
+        let mut pos = 0;
+        while pos < code.len() {
+            let rest = &code[pos..];
+            let (kind, len) = if rest.starts_with(' ') {
+                pos += 1;
+                continue;
+            } else if rest.starts_with("&&") {
+                (TokenKind::Operator(Operator::And), 2)
+            } else if rest.starts_with("||") {
+                (TokenKind::Operator(Operator::Or), 2)
+            } else if rest.starts_with('!') {
+                (TokenKind::Not, 1)
+            } else if rest.starts_with('(') {
+                (TokenKind::Open, 1)
+            } else if rest.starts_with(')') {
+                (TokenKind::Close, 1)
+            } else {
+                let selector = word(rest);
+                let comparison = selector + 2;
+                if selector == 0 || !matches!(rest.get(selector..comparison), Some("==" | "!=")) {
+                    let expected = vec![Expected::Selector, Expected::OpeningParenthesis];
+                    return Err(ParserError::Syntax(SyntaxError::new(code, pos, expected)));
+                }
+                let value = comparison + word(&rest[comparison..]);
+                let arguments = pos + comparison..pos + value;
+                let token = ConstraintToken {
+                    selector: pos..pos + selector,
+                    comparison: pos + selector..pos + comparison,
+                    values: vec![arguments.clone()],
+                    arguments,
+                };
+                (TokenKind::Constraint(token), value)
+            };
+            tokens.push(Token { kind, range: pos..pos + len });
+            pos += len;
+        }
+        Ok(tokens)
+    }
+
+    fn default_comparison(&self, symbol: &str) -> Option<Arc<Comparison>> {
+        match symbol {
+            "==" => Some(Arc::clone(&self.equal)),
+            "!=" => Some(Arc::clone(&self.not_equal)),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn test_custom_dialect() -> anyhow::Result<()> {
+    let parser = QueryParser::<Logic>::default();
+    let expr = parser.parse_to_node("a==1 && !(b!=2 || c==3)")?;
+    assert_eq!(
+        expr,
+        Expr::And(vec![
+            Expr::item("a", Comparison::EQUAL(), &["1"])?,
+            Expr::Not(Box::new(Expr::Or(vec![
+                Expr::item("b", Comparison::NOT_EQUAL(), &["2"])?,
+                Expr::item("c", Comparison::EQUAL(), &["3"])?,
+            ]))),
+        ])
+    );
+
+    // Group matching, operands and limits are checked by the parser, not the dialect
+    match parser.parse_to_node("a==1 &&") {
+        Err(ParserError::Syntax(err)) => assert_eq!(err.position.offset, 7),
+        res => panic!("expected a syntax error, got {:?}", res),
+    }
+    assert!(matches!(parser.parse_to_node("(a==1"), Err(ParserError::Syntax(_))));
+    let parser = QueryParser::new(Logic::default(), ParserOptions::default().max_depth(2));
+    assert!(matches!(
+        parser.parse_to_node("(((a==1)))"),
+        Err(ParserError::NestingTooDeep { max: 2, offset: 2 })
+    ));
+    assert!(matches!(parser.parse_to_node("a=~1"), Err(ParserError::Syntax(_))));
+
+    Ok(())
+}
@@ -1,29 +1,31 @@
+use rsql::borrowed;
+use rsql::error::{Expected, ParserError};
 use rsql::parser::fiql::*;
+use rsql::parser::options::ParserOptions;
 use rsql::parser::rsql::RsqlParser;
 use rsql::parser::Parser;
-use rsql::Comparison;
 use rsql::Expr;
-use rsql::Operator;
+use rsql::Value;
+use rsql::{Arity, Comparison, Formatter};
+use std::borrow::Cow;
+use std::fmt;
 
 #[test]
 fn test_simple() -> anyhow::Result<()> {
     let rsql_parser = RsqlParser::default();
-    let fiql_parser = FiqlParser::default();
+    let fiql_parser = FiqlParser::with_options(ParserOptions::default().percent_decoding(false));
 
     let code = "title==foo*;(updated=lt=-P1D,title==*b%20r)";
-    let node = Expr::Node(
-        Operator::Or,
-        Expr::boxed_item("updated", Comparison::LESS_THAN(), &["-P1D"])?,
-        Expr::boxed_item("title", Comparison::EQUAL(), &["*b%20r"])?,
-    );
-    let node = Expr::Node(
-        Operator::And,
-        Expr::boxed_item("title", Comparison::EQUAL(), &["foo*"])?,
-        Box::new(node),
-    );
+    let node = Expr::And(vec![
+        Expr::item("title", Comparison::EQUAL(), &["foo*"])?,
+        Expr::Or(vec![
+            Expr::item("updated", Comparison::LESS_THAN(), &["-P1D"])?,
+            Expr::item("title", Comparison::EQUAL(), &["*b%20r"])?,
+        ]),
+    ]);
 
-    assert_eq!(fiql_parser.parse_to_node(&code)?, node);
-    assert_eq!(rsql_parser.parse_to_node(&code)?, node);
+    assert_eq!(fiql_parser.parse_to_node(code)?, node);
+    assert_eq!(rsql_parser.parse_to_node(code)?, node);
     assert_eq!(node.to_string(), code);
     assert_eq!(rsql_parser.parse_to_node(&node.to_string())?, node);
 
@@ -37,8 +39,8 @@ fn test_date() -> anyhow::Result<()> {
 
     let code = "updated==2003-12-13T18:30:02Z";
     let node = Expr::boxed_item("updated", Comparison::EQUAL(), &["2003-12-13T18:30:02Z"])?;
-    assert_eq!(Box::new(fiql_parser.parse_to_node(&code)?), node);
-    assert_eq!(Box::new(rsql_parser.parse_to_node(&code)?), node);
+    assert_eq!(Box::new(fiql_parser.parse_to_node(code)?), node);
+    assert_eq!(Box::new(rsql_parser.parse_to_node(code)?), node);
     assert_eq!(node.to_string(), code);
     assert_eq!(&rsql_parser.parse_to_node(&node.to_string())?, node.as_ref());
 
@@ -51,8 +53,8 @@ fn test_number() -> anyhow::Result<()> {
     let fiql_parser = FiqlParser::default();
     let code = "x:foo=gt=500";
     let node = Expr::boxed_item("x:foo", Comparison::GREATER_THAN(), &["500"])?;
-    assert_eq!(Box::new(fiql_parser.parse_to_node(&code)?), node);
-    assert_eq!(Box::new(rsql_parser.parse_to_node(&code)?), node);
+    assert_eq!(Box::new(fiql_parser.parse_to_node(code)?), node);
+    assert_eq!(Box::new(rsql_parser.parse_to_node(code)?), node);
     assert_eq!(node.to_string(), code);
     assert_eq!(&rsql_parser.parse_to_node(&node.to_string())?, node.as_ref());
     Ok(())
@@ -63,8 +65,8 @@ fn test_parentheses_lacking() -> anyhow::Result<()> {
     let rsql_parser = RsqlParser::default();
     let fiql_parser = FiqlParser::default();
     let code = "(lack==of_paren";
-    assert!(fiql_parser.parse_to_node(&code).is_err());
-    assert!(rsql_parser.parse_to_node(&code).is_err());
+    assert!(fiql_parser.parse_to_node(code).is_err());
+    assert!(rsql_parser.parse_to_node(code).is_err());
     Ok(())
 }
 
@@ -73,15 +75,15 @@ fn test_invalid_selector() -> anyhow::Result<()> {
     let _rsql_parser = RsqlParser::default();
     let fiql_parser = FiqlParser::default();
     let code = "$invalid==argument";
-    assert!(fiql_parser.parse_to_node(&code).is_err());
+    assert!(fiql_parser.parse_to_node(code).is_err());
     let code = "!invalid==argument";
-    assert!(fiql_parser.parse_to_node(&code).is_err());
+    assert!(fiql_parser.parse_to_node(code).is_err());
     let code = "*invalid==argument";
-    assert!(fiql_parser.parse_to_node(&code).is_err());
+    assert!(fiql_parser.parse_to_node(code).is_err());
     let code = "+invalid==argument";
-    assert!(fiql_parser.parse_to_node(&code).is_err());
+    assert!(fiql_parser.parse_to_node(code).is_err());
     let code = "'invalid==argument";
-    assert!(fiql_parser.parse_to_node(&code).is_err());
+    assert!(fiql_parser.parse_to_node(code).is_err());
 
     Ok(())
 }
@@ -91,17 +93,17 @@ fn test_invalid_comparison() -> anyhow::Result<()> {
     let rsql_parser = RsqlParser::default();
     let fiql_parser = FiqlParser::default();
     let code = "key=!=value";
-    assert!(fiql_parser.parse_to_node(&code).is_err());
-    assert!(rsql_parser.parse_to_node(&code).is_err());
+    assert!(fiql_parser.parse_to_node(code).is_err());
+    assert!(rsql_parser.parse_to_node(code).is_err());
     let code = "key=~=value";
-    assert!(fiql_parser.parse_to_node(&code).is_err());
-    assert!(rsql_parser.parse_to_node(&code).is_err());
+    assert!(fiql_parser.parse_to_node(code).is_err());
+    assert!(rsql_parser.parse_to_node(code).is_err());
     let code = "key=notfound=value";
-    assert!(fiql_parser.parse_to_node(&code).is_err());
-    assert!(rsql_parser.parse_to_node(&code).is_err());
+    assert!(fiql_parser.parse_to_node(code).is_err());
+    assert!(rsql_parser.parse_to_node(code).is_err());
     let code = "key<>=value";
-    assert!(fiql_parser.parse_to_node(&code).is_err());
-    assert!(rsql_parser.parse_to_node(&code).is_err());
+    assert!(fiql_parser.parse_to_node(code).is_err());
+    assert!(rsql_parser.parse_to_node(code).is_err());
 
     Ok(())
 }
@@ -109,33 +111,244 @@ fn test_invalid_comparison() -> anyhow::Result<()> {
 #[test]
 fn test_complex() -> anyhow::Result<()> {
     let rsql_parser = RsqlParser::default();
-    let fiql_parser = FiqlParser::default();
+    let fiql_parser = FiqlParser::with_options(ParserOptions::default().percent_decoding(false));
     let code = "updated==2003-12-13T18:30:02Z;(director==Christopher%20Nolan,(actor==*Bale;\
                 year=ge=1.234),content==*just%20the%20start*)";
-    let actor_year = Expr::Node(
-        Operator::And,
-        Expr::boxed_item("actor", Comparison::EQUAL(), &["*Bale"])?,
-        Expr::boxed_item("year", Comparison::GREATER_THAN_OR_EQUAL(), &["1.234"])?,
+    let actor_year = Expr::And(vec![
+        Expr::item("actor", Comparison::EQUAL(), &["*Bale"])?,
+        Expr::item("year", Comparison::GREATER_THAN_OR_EQUAL(), &["1.234"])?,
+    ]);
+    let res = Expr::And(vec![
+        Expr::item("updated", Comparison::EQUAL(), &["2003-12-13T18:30:02Z"])?,
+        Expr::Or(vec![
+            Expr::item("director", Comparison::EQUAL(), &["Christopher%20Nolan"])?,
+            actor_year,
+            Expr::item("content", Comparison::EQUAL(), &["*just%20the%20start*"])?,
+        ]),
+    ]);
+
+    assert_eq!(fiql_parser.parse_to_node(code)?, res);
+    assert_eq!(rsql_parser.parse_to_node(code)?, res);
+    assert_eq!(
+        res.to_string(),
+        "updated==2003-12-13T18:30:02Z;(director==Christopher%20Nolan,actor==*Bale;\
+         year=ge=1.234,content==*just%20the%20start*)"
     );
-    let res = Expr::Node(
-        Operator::Or,
-        Expr::boxed_item("director", Comparison::EQUAL(), &["Christopher%20Nolan"])?,
-        Box::new(actor_year),
+
+    Ok(())
+}
+
+#[test]
+fn test_spans() -> anyhow::Result<()> {
+    let parser = FiqlParser::default();
+    let code = "title==foo*;(updated=lt=-P1D,title==*b%20r)";
+    let (node, source_map) = parser.parse_with_spans(code)?;
+    assert_eq!(node, parser.parse_to_node(code)?);
+
+    let spans = source_map
+        .constraints
+        .iter()
+        .map(|c| (c.selector.as_str(code), c.comparison.as_str(code), c.arguments.as_str(code)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        spans,
+        [("title", "==", "foo*"), ("updated", "=lt=", "-P1D"), ("title", "==", "*b%20r")]
     );
-    let res = Expr::Node(
-        Operator::Or,
-        Box::new(res),
-        Expr::boxed_item("content", Comparison::EQUAL(), &["*just%20the%20start*"])?,
+    assert_eq!(source_map.constraints[2].values[0].range(), 36..42);
+    assert_eq!(source_map.constraints[2].values[0].start.column, 37);
+    assert_eq!(source_map.operators.len(), 2);
+    assert_eq!(source_map.groups[0].range(), 12..code.len());
+
+    Ok(())
+}
+
+#[test]
+fn test_syntax_error() -> anyhow::Result<()> {
+    let parser = FiqlParser::default();
+
+    let err = match parser.parse_to_node("title==foo*;") {
+        Err(ParserError::Syntax(err)) => err,
+        res => panic!("unexpected result: {:?}", res),
+    };
+    assert_eq!(err.position.offset, 12);
+    assert_eq!(err.expected, [Expected::Selector, Expected::OpeningParenthesis]);
+
+    let err = match parser.parse_to_node("title=foo") {
+        Err(ParserError::Syntax(err)) => err,
+        res => panic!("unexpected result: {:?}", res),
+    };
+    assert_eq!(err.position.column, 6);
+    assert_eq!(err.snippet, "=foo");
+    // `title` alone is a bare selector
+    assert_eq!(
+        err.expected,
+        [Expected::ComparisonOperator, Expected::LogicalOperator, Expected::EndOfInput]
     );
-    let res = Expr::Node(
-        Operator::And,
-        Expr::boxed_item("updated", Comparison::EQUAL(), &["2003-12-13T18:30:02Z"])?,
-        Box::new(res),
+
+    Ok(())
+}
+
+#[test]
+fn test_recovering() -> anyhow::Result<()> {
+    let parser = FiqlParser::default();
+
+    let code = "title=foo*;(updated=lt=-P1D,title=~=*b%20r";
+    let recovered = parser.parse_recovering(code);
+    assert_eq!(
+        recovered.expr,
+        Some(*Expr::boxed_item("updated", Comparison::LESS_THAN(), &["-P1D"])?)
     );
+    let spans = recovered
+        .diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.span.as_str(code))
+        .collect::<Vec<_>>();
+    assert_eq!(spans, ["title=foo*", "(", "title=~=*b%20r"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_percent_decoding() -> anyhow::Result<()> {
+    let parser = FiqlParser::default();
+
+    let code = "title==*b%20r;x%3Afoo=gt=caf%C3%A9%2Fbar%25";
+    let node = Expr::And(vec![
+        Expr::item("title", Comparison::EQUAL(), &[Value::Unquoted("*b r".to_string())])?,
+        Expr::item("x:foo", Comparison::GREATER_THAN(), &["caf\u{e9}/bar%"])?,
+    ]);
+    assert_eq!(parser.parse_to_node(code)?, node);
+    assert_eq!(node.to_fiql_string()?, "title==*b%20r;x:foo=gt=caf%C3%A9%2Fbar%25");
+    assert_eq!(parser.parse_to_node(&node.to_fiql_string()?)?, node);
+    // RSQL needs quotes for the decoded space, which the strict formatter refuses
+    assert_eq!(node.to_string(), "title=='*b r';x:foo=gt=caf\u{e9}/bar%");
+    assert_eq!(Formatter::rsql().format(&node), Err(fmt::Error));
+    // Selectors have no quotes in RSQL
+    let selector = parser.parse_to_node("a%20b==1")?;
+    assert_eq!(Formatter::rsql().format(&selector), Err(fmt::Error));
+    assert_eq!(selector.to_string(), "a b==1");
+
+    assert!(matches!(
+        parser.parse_to_node("title==%C3%28"),
+        Err(ParserError::InvalidPercentEncoding(value)) if value == "%C3%28"
+    ));
+    assert!(FiqlParser::with_options(ParserOptions::default().percent_decoding(false))
+        .parse_to_node("title==%C3%28")
+        .is_ok());
+
+    match &parser.parse_borrowed("title==b%20r")? {
+        borrowed::Expr::Item(constraint) => {
+            assert!(matches!(constraint.selector, Cow::Borrowed("title")));
+            assert!(matches!(
+                constraint.arguments.0[..],
+                [borrowed::Value::Unquoted(Cow::Owned(ref arg))] if arg == "b r"
+            ));
+        }
+        expr => panic!("unexpected expr: {:?}", expr),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_exists() -> anyhow::Result<()> {
+    let parser = FiqlParser::default();
+    let code = "premium;(title==foo*,x%3Ay)";
+    let node = Expr::And(vec![
+        Expr::Exists("premium".to_string()),
+        Expr::Or(vec![
+            Expr::item("title", Comparison::EQUAL(), &["foo*"])?,
+            Expr::Exists("x:y".to_string()),
+        ]),
+    ]);
+    assert_eq!(parser.parse_to_node(code)?, node);
+    assert_eq!(node.to_fiql_string()?, "premium;(title==foo*,x:y)");
+    assert_eq!(node.constraints().len(), 1);
+    assert_eq!(serde_json::from_str::<Expr>(&serde_json::to_string(&node)?)?, node);
+    assert_eq!(serde_json::to_value(&node)?["@data"][0]["@type"], "Exists");
+
+    // RSQL has no bare selectors, so they cannot be formatted in RSQL, `Display` writes them as
+    // in FIQL
+    assert!(RsqlParser::default().parse_to_node("premium").is_err());
+    assert_eq!(Formatter::rsql().format(&node), Err(fmt::Error));
+    assert_eq!(node.to_string(), "premium;(title==foo*,x:y)");
+    assert_eq!(parser.parse_to_node("premium")?.to_string(), "premium");
 
-    assert_eq!(fiql_parser.parse_to_node(&code)?, res);
-    assert_eq!(rsql_parser.parse_to_node(&code)?, res);
-    assert_eq!(res.to_string(), code);
+    let (_, source_map) = parser.parse_with_spans(code)?;
+    let exists = source_map.exists.iter().map(|span| span.as_str(code));
+    assert_eq!(exists.collect::<Vec<_>>(), ["premium", "x%3Ay"]);
+    assert_eq!(source_map.constraints.len(), 1);
 
+    let recovered = parser.parse_recovering("premium;title=foo");
+    assert_eq!(recovered.expr, Some(Expr::Exists("premium".to_string())));
+    assert_eq!(recovered.diagnostics[0].span.as_str("premium;title=foo"), "title=foo");
+    assert!(matches!(
+        FiqlParser::with_options(ParserOptions::default().max_constraints(1)).parse_to_node("a;b"),
+        Err(ParserError::TooManyConstraints { max: 1 })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_argument_lists() -> anyhow::Result<()> {
+    let mut parser = FiqlParser::default();
+    assert!(matches!(
+        parser.parse_to_node("genres=in=(sci-fi,action)"),
+        Err(ParserError::InvalidComparison(ref comp)) if comp == "=in="
+    ));
+    parser.register_comparison(&Comparison::IN())?;
+
+    let code = "genres=in=(sci-fi,action%2A);year=in=(2003)";
+    let node = Expr::And(vec![
+        Expr::item("genres", Comparison::IN(), &["sci-fi", "action*"])?,
+        Expr::item("year", Comparison::IN(), &["2003"])?,
+    ]);
+    assert_eq!(parser.parse_to_node(code)?, node);
+    assert_eq!(node.to_fiql_string()?, "genres=in=(sci-fi,action*);year=in=(2003)");
+    assert_eq!(parser.parse_to_node(&node.to_fiql_string()?)?, node);
+
+    let (_, source_map) = parser.parse_with_spans(code)?;
+    assert_eq!(source_map.constraints[0].arguments.as_str(code), "(sci-fi,action%2A)");
+    let values = source_map.constraints[0].values.iter().map(|value| value.as_str(code));
+    assert_eq!(values.collect::<Vec<_>>(), ["sci-fi", "action%2A"]);
+    assert!(source_map.groups.is_empty());
+
+    assert!(matches!(
+        parser.parse_to_node("title==(foo,bar)"),
+        Err(ParserError::InvalidConstraintArgs { count: 2, .. })
+    ));
+    match parser.parse_to_node("genres=in=(sci-fi,") {
+        Err(ParserError::Syntax(err)) => assert_eq!(err.expected, [Expected::Argument]),
+        res => panic!("unexpected result: {:?}", res),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_custom_arity() -> anyhow::Result<()> {
+    let between = Comparison::new(&["=between="], Arity::Exact(2))?;
+    let is_null = Comparison::new(&["=isnull="], Arity::Range { min: 0, max: 1 })?;
+    let mut parser = FiqlParser::default();
+    parser.register_comparison(&between)?;
+    parser.register_comparison(&is_null)?;
+
+    let code = "title=isnull=;(year=between=(1,10),title=isnull=)";
+    let expr = parser.parse_to_node(code)?;
+    assert_eq!(
+        expr,
+        Expr::And(vec![
+            Expr::item("title", is_null.clone(), &[] as &[&str])?,
+            Expr::Or(vec![
+                Expr::item("year", between, &["1", "10"])?,
+                Expr::item("title", is_null, &[] as &[&str])?,
+            ]),
+        ])
+    );
+    assert_eq!(expr.to_fiql_string()?, "title=isnull=();(year=between=(1,10),title=isnull=())");
+    assert_eq!(parser.parse_to_node(&expr.to_fiql_string()?)?, expr);
+    assert!(matches!(
+        parser.parse_to_node("title=="),
+        Err(ParserError::InvalidConstraintArgs { count: 0, .. })
+    ));
     Ok(())
 }
@@ -0,0 +1,310 @@
+use regex::RegexSet;
+use rsql::error::{Expected, ParserError};
+use rsql::parser::fiql::FiqlParser;
+use rsql::parser::options::{ParserOptions, DEFAULT_SYMBOL_PATTERNS};
+use rsql::parser::rsql::RsqlParser;
+use rsql::parser::Parser;
+use rsql::Expr;
+use rsql::{Arity, Comparison, Value};
+
+fn syntax_error(res: Result<Expr, ParserError>) -> (usize, Vec<Expected>) {
+    match res {
+        Err(ParserError::Syntax(err)) => (err.position.offset, err.expected),
+        res => panic!("expected a syntax error, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_keyword_operators() -> anyhow::Result<()> {
+    let expected = Expr::Or(vec![
+        Expr::item("a", Comparison::EQUAL(), &["1"])?,
+        Expr::Not(Box::new(Expr::item("b", Comparison::EQUAL(), &["2"])?)),
+    ]);
+
+    let parser = RsqlParser::default();
+    assert_eq!(parser.parse_to_node("a==1 or not b==2")?, expected);
+    assert_eq!(
+        syntax_error(parser.parse_to_node("a==1 OR b==2")),
+        (5, vec![Expected::LogicalOperator])
+    );
+
+    let parser = RsqlParser::with_options(ParserOptions::default().keyword_operators(false));
+    assert_eq!(parser.parse_to_node("a==1,!b==2")?, expected);
+    assert_eq!(
+        syntax_error(parser.parse_to_node("a==1 or !b==2")),
+        (5, vec![Expected::LogicalOperator])
+    );
+    assert_eq!(
+        syntax_error(parser.parse_to_node("a==1,not b==2")),
+        (5, vec![Expected::Selector, Expected::OpeningParenthesis])
+    );
+    let recovered = parser.parse_recovering("a==1 or b==2");
+    assert_eq!(recovered.diagnostics.len(), 1);
+    assert_eq!(recovered.expr, Some(parser.parse_to_node("a==1,b==2")?));
+
+    let parser = RsqlParser::with_options(ParserOptions::default().case_insensitive_keywords(true));
+    assert_eq!(parser.parse_to_node("a==1 OR Not b==2")?, expected);
+
+    // Selectors starting with a keyword are not split after it
+    let parser = RsqlParser::default();
+    for code in &["a==1 android==2", "a==1 order==2", "a==1 AND_x==2"] {
+        let expected = vec![Expected::LogicalOperator, Expected::EndOfInput];
+        assert_eq!(syntax_error(parser.parse_to_node(code)), (5, expected));
+    }
+    let expected = Expr::Or(vec![
+        Expr::item("android", Comparison::EQUAL(), &["1"])?,
+        Expr::item("order", Comparison::EQUAL(), &["2"])?,
+    ]);
+    assert_eq!(parser.parse_to_node("android==1 or order==2")?, expected);
+    assert_eq!(parser.parse_to_node("android==1 or(order==2)")?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_short_comparisons() -> anyhow::Result<()> {
+    let parser = RsqlParser::with_options(ParserOptions::default().short_comparisons(false));
+    assert_eq!(
+        parser.parse_to_node("year=gt=2000")?,
+        Expr::item("year", Comparison::GREATER_THAN(), &["2000"])?
+    );
+    for code in &["year>2000", "year>=2000", "year<2000", "year<=2000"] {
+        assert!(matches!(
+            parser.parse_to_node(code),
+            Err(ParserError::InvalidComparison(ref comp)) if code.contains(comp.as_str())
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_whitespace() -> anyhow::Result<()> {
+    let parser = RsqlParser::with_options(ParserOptions::default().whitespace(false));
+    assert_eq!(
+        parser.parse_to_node("name=='Kill Bill';year>2003")?,
+        RsqlParser::default().parse_to_node("name=='Kill Bill' ; year > 2003")?
+    );
+    assert_eq!(syntax_error(parser.parse_to_node("name=='Kill Bill' ;year>2003")).0, 17);
+    assert_eq!(syntax_error(parser.parse_to_node(" a==1")).0, 0);
+    assert_eq!(syntax_error(parser.parse_to_node("a==1 ")).0, 4);
+    assert_eq!(syntax_error(parser.parse_to_node("a==1;\nb==2")).0, 5);
+
+    // Line breaks are whitespace, and end unquoted values like spaces and tabs
+    assert_eq!(
+        RsqlParser::default().parse_to_node("(\r\n  a==1\tand\n  not\nb=in=(x, y)\n)")?,
+        RsqlParser::default().parse_to_node("a==1;!b=in=(x,y)")?
+    );
+
+    let recovered = parser.parse_recovering("a== 1;b==2 ");
+    assert_eq!(
+        recovered.diagnostics.iter().map(|diagnostic| diagnostic.span.range()).collect::<Vec<_>>(),
+        [3..4, 10..11]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_quoted_values() -> anyhow::Result<()> {
+    let parser = RsqlParser::with_options(ParserOptions::default().quoted_values(false));
+    assert_eq!(
+        parser.parse_to_node("genres=in=(sci-fi,action)")?,
+        Expr::item("genres", Comparison::IN(), &["sci-fi", "action"])?
+    );
+    assert_eq!(
+        syntax_error(parser.parse_to_node("genres=in=(sci-fi,'action')")),
+        (18, vec![Expected::Argument])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_default_comparisons() -> anyhow::Result<()> {
+    let like = Comparison::new(&["=like="], Arity::Exact(1))?;
+    let options = ParserOptions::default().default_comparisons(false);
+
+    let mut parser = RsqlParser::with_options(options.clone());
+    parser.register_comparison(&Comparison::GREATER_THAN())?;
+    parser.register_comparison(&like)?;
+    parser.register_comparison(&Comparison::EQUAL())?;
+    assert_eq!(parser.parse_to_node("a=like=b*")?, Expr::item("a", like.clone(), &["b*"])?);
+    assert_eq!(parser.parse_to_node("a==b")?, Expr::item("a", Comparison::EQUAL(), &["b"])?);
+    assert!(matches!(
+        parser.parse_to_node("a=in=(b,c)"),
+        Err(ParserError::InvalidComparison(ref comp)) if comp == "=in="
+    ));
+
+    let mut parser = FiqlParser::with_options(options);
+    parser.register_comparison(&like)?;
+    assert!(parser.parse_to_node("a=like=b*").is_ok());
+    assert!(matches!(
+        parser.parse_to_node("a=gt=b"),
+        Err(ParserError::InvalidComparison(ref comp)) if comp == "=gt="
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_limits() -> anyhow::Result<()> {
+    let parser = RsqlParser::default();
+    let nested = |depth: usize| format!("{}a==1{}", "(".repeat(depth), ")".repeat(depth));
+    assert_eq!(parser.parse_to_node(&nested(64))?, Expr::item("a", Comparison::EQUAL(), &["1"])?);
+    assert!(matches!(
+        parser.parse_to_node(&nested(1_000_000)),
+        Err(ParserError::NestingTooDeep { max: 64, offset: 64 })
+    ));
+    assert!(matches!(
+        FiqlParser::default().parse_to_node(&nested(1_000_000)),
+        Err(ParserError::NestingTooDeep { max: 64, offset: 64 })
+    ));
+    assert!(matches!(
+        parser.parse_recovering(&nested(1_000_000)).diagnostics[0].error,
+        ParserError::NestingTooDeep { .. }
+    ));
+    assert!(matches!(
+        parser.parse_to_node(&format!("{}a==1", "!".repeat(100))),
+        Err(ParserError::NestingTooDeep { max: 64, offset: 64 })
+    ));
+
+    let options = ParserOptions::default()
+        .max_length(40)
+        .max_constraints(3)
+        .max_arguments(2)
+        .max_argument_length(5);
+    let parser = RsqlParser::with_options(options.clone());
+    assert!(parser.parse_to_node("a=in=(12345,x);b==1;c==2").is_ok());
+    assert!(matches!(
+        parser.parse_to_node(&"a==1;".repeat(10)),
+        Err(ParserError::InputTooLong { max: 40, length: 50 })
+    ));
+    assert!(matches!(
+        parser.parse_to_node("a==1;b==1;c==1;d==1"),
+        Err(ParserError::TooManyConstraints { max: 3 })
+    ));
+    assert!(matches!(
+        parser.parse_to_node("a=in=(1,2,3)"),
+        Err(ParserError::TooManyArguments { max: 2, count: 3 })
+    ));
+    assert!(matches!(
+        parser.parse_to_node("a=='\\u00e9\\u00e9\\u00e9'"),
+        Err(ParserError::ArgumentTooLong { max: 5, length: 6 })
+    ));
+    assert!(matches!(
+        FiqlParser::with_options(options).parse_to_node("a==%C3%A9%C3%A9%C3%A9"),
+        Err(ParserError::ArgumentTooLong { max: 5, length: 6 })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_symbol_patterns() -> anyhow::Result<()> {
+    let regex = Comparison::new(&["=~", "=regex="], Arity::Exact(1))?;
+    let contains = Comparison::new(&["=@"], Arity::Exact(1))?;
+    let in2 = Comparison::new(&["=in2="], Arity::AtLeast(1))?;
+
+    let mut parser = RsqlParser::default();
+    assert!(matches!(
+        parser.register_comparison(&regex),
+        Err(ParserError::InvalidComparison(ref comp)) if comp == "=~"
+    ));
+    assert!(parser.get_comparison("=regex=").is_none());
+
+    let mut patterns = DEFAULT_SYMBOL_PATTERNS.to_vec();
+    patterns.extend(&[r"^[=!]~$", r"^=@$", r"^=[a-z0-9]+=$", r"^<~$", r"^==@$"]);
+    let options = ParserOptions::default().symbol_patterns(RegexSet::new(patterns)?);
+    for mut parser in [
+        Box::new(RsqlParser::with_options(options.clone())) as Box<dyn Parser>,
+        Box::new(FiqlParser::with_options(options.clone())),
+    ] {
+        parser.register_comparison(&regex)?;
+        parser.register_comparison(&contains)?;
+        parser.register_comparison(&in2)?;
+        assert_eq!(
+            parser.parse_to_node("name=~a.*;mail=@example;id=in2=(1,2)")?,
+            Expr::And(vec![
+                Expr::item("name", regex.clone(), &["a.*"])?,
+                Expr::item("mail", contains.clone(), &["example"])?,
+                Expr::item("id", in2.clone(), &["1", "2"])?,
+            ])
+        );
+
+        // `==` would be read first
+        assert!(matches!(
+            parser.register_comparison(&Comparison::new(&["==@"], Arity::Exact(1))?),
+            Err(ParserError::AmbiguousComparison(ref comp)) if comp == "==@"
+        ));
+        assert!(matches!(
+            parser.register_comparison(&Comparison::new(&["=like=", "=~"], Arity::Exact(1))?),
+            Err(ParserError::ConflictingComparison(ref comp)) if comp == "=~"
+        ));
+        assert!(parser.get_comparison("=like=").is_none());
+        assert!(matches!(
+            parser.register_comparison(&Comparison::new(&["=gt="], Arity::Exact(1))?),
+            Err(ParserError::ConflictingComparison(_))
+        ));
+    }
+    let mut parser = RsqlParser::with_options(options.clone());
+    parser.register_comparison(&Comparison::GREATER_THAN())?;
+    assert_eq!(parser.parse_to_node("mail==@example")?.to_string(), "mail==@example");
+    let less_like = Comparison::new(&["<~"], Arity::Exact(1))?;
+    assert!(matches!(
+        parser.register_comparison(&less_like),
+        Err(ParserError::AmbiguousComparison(_))
+    ));
+    let mut parser = FiqlParser::with_options(options.clone().short_comparisons(false));
+    parser.register_comparison(&less_like)?;
+    assert_eq!(parser.parse_to_node("a<~b")?, Expr::item("a", less_like, &["b"])?);
+
+    // Argument lists of registered symbols are not groups
+    let mut parser = RsqlParser::with_options(options.max_depth(1));
+    parser.register_comparison(&regex)?;
+    assert_eq!(parser.parse_to_node("(name=~(a.*))")?, Expr::item("name", regex, &["a.*"])?);
+    assert!(matches!(
+        parser.parse_to_node("((name=~(a.*)))"),
+        Err(ParserError::NestingTooDeep { max: 1, offset: 1 })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_typed_values() -> anyhow::Result<()> {
+    let code = r#"year=in=(2003,"2003",02003);deleted==null;ok==true;price<1.5"#;
+    let parser = RsqlParser::with_options(ParserOptions::default().typed_values(true));
+    let expr = parser.parse_to_node(code)?;
+    let typed = Expr::And(vec![
+        Expr::item(
+            "year",
+            Comparison::IN(),
+            &[
+                Value::Integer(2003),
+                Value::String("2003".to_string()),
+                Value::Unquoted("02003".to_string()),
+            ],
+        )?,
+        Expr::item("deleted", Comparison::EQUAL(), &[Value::Null])?,
+        Expr::item("ok", Comparison::EQUAL(), &[true])?,
+        Expr::item("price", Comparison::LESS_THAN(), &[Value::Float(1.5)])?,
+    ]);
+    assert_eq!(expr, typed);
+    assert_eq!(
+        expr.to_string(),
+        r#"year=in=(2003,'2003',02003);deleted==null;ok==true;price=lt=1.5"#
+    );
+    assert_eq!(parser.parse_to_node(&expr.to_string())?, expr);
+
+    let json = serde_json::to_string(&expr)?;
+    assert!(json.contains(r#"[{"@type":"Integer","@data":2003},{"@type":"String","@data":"2003"}"#));
+    assert_eq!(serde_json::from_str::<Expr>(&json)?, expr);
+    assert_eq!(serde_json::to_string(&parser.parse_borrowed(code)?)?, json);
+
+    let untyped = RsqlParser::default().parse_to_node(code)?;
+    assert_eq!(untyped.to_string(), expr.to_string());
+    assert!(matches!(
+        &untyped,
+        Expr::And(children) if matches!(
+            &children[1],
+            Expr::Item(constraint) if constraint.arguments.0 == [Value::Unquoted("null".to_string())]
+        )
+    ));
+    Ok(())
+}
@@ -0,0 +1,244 @@
+use proptest::collection::vec;
+use proptest::prelude::*;
+use rsql::parser::fiql::FiqlParser;
+use rsql::parser::rsql::RsqlParser;
+use rsql::parser::Parser;
+use rsql::Comparison;
+use rsql::Expr;
+use rsql::Value;
+use rsql::{ComparisonStyle, Formatter, QuoteStyle};
+
+fn item(selector: &str) -> anyhow::Result<Expr> {
+    Ok(Expr::item(selector, Comparison::EQUAL(), &["1"])?)
+}
+
+fn assert_both(code: &str, expected: &Expr) -> anyhow::Result<()> {
+    assert_eq!(&RsqlParser::default().parse_to_node(code)?, expected, "RSQL: {}", code);
+    assert_eq!(&FiqlParser::default().parse_to_node(code)?, expected, "FIQL: {}", code);
+    assert_eq!(&RsqlParser::default().parse_to_node(&expected.to_string())?, expected);
+    Ok(())
+}
+
+#[test]
+fn test_same_operator() -> anyhow::Result<()> {
+    assert_both("a==1;b==1;c==1", &Expr::And(vec![item("a")?, item("b")?, item("c")?]))?;
+    assert_both("a==1,b==1,c==1", &Expr::Or(vec![item("a")?, item("b")?, item("c")?]))?;
+    Ok(())
+}
+
+#[test]
+fn test_and_over_or() -> anyhow::Result<()> {
+    assert_both(
+        "a==1,b==1;c==1",
+        &Expr::Or(vec![item("a")?, Expr::And(vec![item("b")?, item("c")?])]),
+    )?;
+    assert_both(
+        "a==1;b==1,c==1",
+        &Expr::Or(vec![Expr::And(vec![item("a")?, item("b")?]), item("c")?]),
+    )?;
+    assert_both(
+        "a==1;b==1,c==1;d==1",
+        &Expr::Or(vec![
+            Expr::And(vec![item("a")?, item("b")?]),
+            Expr::And(vec![item("c")?, item("d")?]),
+        ]),
+    )?;
+    assert_both(
+        "a==1,b==1;c==1,d==1",
+        &Expr::Or(vec![item("a")?, Expr::And(vec![item("b")?, item("c")?]), item("d")?]),
+    )?;
+    assert_both(
+        "a==1,b==1;c==1;d==1,e==1",
+        &Expr::Or(vec![
+            item("a")?,
+            Expr::And(vec![item("b")?, item("c")?, item("d")?]),
+            item("e")?,
+        ]),
+    )?;
+    Ok(())
+}
+
+#[test]
+fn test_groups() -> anyhow::Result<()> {
+    assert_both(
+        "(a==1,b==1);c==1",
+        &Expr::And(vec![Expr::Or(vec![item("a")?, item("b")?]), item("c")?]),
+    )?;
+    assert_both(
+        "a==1;(b==1,c==1)",
+        &Expr::And(vec![item("a")?, Expr::Or(vec![item("b")?, item("c")?])]),
+    )?;
+    assert_both(
+        "(a==1;b==1),c==1",
+        &Expr::Or(vec![Expr::And(vec![item("a")?, item("b")?]), item("c")?]),
+    )?;
+    assert_both(
+        "(a==1,b==1);(c==1,d==1)",
+        &Expr::And(vec![
+            Expr::Or(vec![item("a")?, item("b")?]),
+            Expr::Or(vec![item("c")?, item("d")?]),
+        ]),
+    )?;
+    assert_both(
+        "((a==1,b==1));c==1",
+        &Expr::And(vec![Expr::Or(vec![item("a")?, item("b")?]), item("c")?]),
+    )?;
+    assert_both(
+        "a==1;(b==1,(c==1;d==1,e==1))",
+        &Expr::And(vec![
+            item("a")?,
+            Expr::Or(vec![
+                item("b")?,
+                Expr::Or(vec![Expr::And(vec![item("c")?, item("d")?]), item("e")?]),
+            ]),
+        ]),
+    )?;
+    assert_both(
+        "a==1;(b==1;c==1)",
+        &Expr::And(vec![item("a")?, Expr::And(vec![item("b")?, item("c")?])]),
+    )?;
+    assert_both("(a==1)", &item("a")?)?;
+    Ok(())
+}
+
+#[test]
+fn test_keywords() -> anyhow::Result<()> {
+    let parser = RsqlParser::default();
+    assert_eq!(
+        parser.parse_to_node("a==1 or b==1 and c==1")?,
+        Expr::Or(vec![item("a")?, Expr::And(vec![item("b")?, item("c")?])])
+    );
+    assert_eq!(
+        parser.parse_to_node("a==1 and b==1 or c==1")?,
+        Expr::Or(vec![Expr::And(vec![item("a")?, item("b")?]), item("c")?])
+    );
+    assert_eq!(
+        parser.parse_to_node("a==1 or b==1;c==1 and d==1")?,
+        Expr::Or(vec![item("a")?, Expr::And(vec![item("b")?, item("c")?, item("d")?])])
+    );
+    assert_eq!(
+        parser.parse_to_node("(a==1 or b==1) and c==1")?,
+        Expr::And(vec![Expr::Or(vec![item("a")?, item("b")?]), item("c")?])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_minimal_parentheses() -> anyhow::Result<()> {
+    let and = |children: Vec<Expr>| Expr::And(children);
+    let or = |children: Vec<Expr>| Expr::Or(children);
+    let not = |child: Expr| Expr::Not(Box::new(child));
+    let cases = vec![
+        (or(vec![and(vec![item("a")?, item("b")?]), item("c")?]), "a==1;b==1,c==1"),
+        (or(vec![item("a")?, and(vec![item("b")?, item("c")?])]), "a==1,b==1;c==1"),
+        (and(vec![or(vec![item("a")?, item("b")?]), item("c")?]), "(a==1,b==1);c==1"),
+        (and(vec![item("a")?, or(vec![item("b")?, item("c")?])]), "a==1;(b==1,c==1)"),
+        (and(vec![not(or(vec![item("a")?, item("b")?])), item("c")?]), "!(a==1,b==1);c==1"),
+        (or(vec![not(item("a")?), not(not(item("b")?))]), "!a==1,!!b==1"),
+        (and(vec![item("a")?, and(vec![item("b")?, item("c")?])]), "a==1;(b==1;c==1)"),
+    ];
+    for (expr, code) in cases {
+        assert_eq!(expr.to_string(), code);
+        assert_eq!(RsqlParser::default().parse_to_node(code)?, expr);
+    }
+    Ok(())
+}
+
+fn tree(leaf: BoxedStrategy<Expr>, negation: bool) -> impl Strategy<Value = Expr> {
+    // Single children are printed without their node, so every node has at least two
+    leaf.prop_recursive(5, 48, 4, move |inner| {
+        let and = vec(inner.clone(), 2..5).prop_map(Expr::And);
+        let or = vec(inner.clone(), 2..5).prop_map(Expr::Or);
+        let not = inner.prop_map(|child| Expr::Not(Box::new(child)));
+        if negation {
+            prop_oneof![and, or, not].boxed()
+        } else {
+            prop_oneof![and, or].boxed()
+        }
+    })
+}
+
+fn comparison() -> impl Strategy<Value = Comparison> {
+    prop_oneof![
+        Just(Comparison::EQUAL()),
+        Just(Comparison::NOT_EQUAL()),
+        Just(Comparison::GREATER_THAN()),
+        Just(Comparison::LESS_THAN_OR_EQUAL()),
+        Just(Comparison::IN()),
+    ]
+}
+
+fn constraint(selector: &'static str, value: BoxedStrategy<Value>) -> BoxedStrategy<Expr> {
+    (selector, comparison(), vec(value, 1..4))
+        .prop_map(|(selector, comparison, mut values)| {
+            if !comparison.is_multi() {
+                values.truncate(1);
+            }
+            Expr::item(&selector, comparison, &values).unwrap()
+        })
+        .boxed()
+}
+
+fn rsql_tree() -> impl Strategy<Value = Expr> {
+    tree(
+        constraint(
+            "[a-z_][a-z0-9_.]{0,3}",
+            prop_oneof![
+                "[a-z0-9*.:-]{1,5}".prop_map(Value::Unquoted),
+                "\\PC{0,5}".prop_map(Value::String),
+            ]
+            .boxed(),
+        ),
+        true,
+    )
+}
+
+fn formatter() -> impl Strategy<Value = Formatter> {
+    let comparisons = prop_oneof![
+        Just(ComparisonStyle::First),
+        Just(ComparisonStyle::Fiql),
+        Just(ComparisonStyle::Symbolic)
+    ];
+    let quotes =
+        prop_oneof![Just(QuoteStyle::Shortest), Just(QuoteStyle::Single), Just(QuoteStyle::Double)];
+    (any::<bool>(), comparisons, quotes, any::<bool>()).prop_map(
+        |(keywords, comparisons, quotes, spaces)| {
+            Formatter::rsql()
+                .keyword_operators(keywords)
+                .comparison_style(comparisons)
+                .quote_style(quotes)
+                .spaces(spaces)
+        },
+    )
+}
+
+proptest! {
+    #[test]
+    fn test_rsql_roundtrip(expr in rsql_tree()) {
+        let code = expr.to_string();
+        prop_assert_eq!(RsqlParser::default().parse_to_node(&code).ok(), Some(expr), "{}", code);
+    }
+
+    #[test]
+    fn test_pretty_roundtrip(expr in rsql_tree(), formatter in formatter(), width in 0..60usize, indent in 0..4usize) {
+        let code = formatter.max_width(width).indent(indent).format(&expr)?;
+        prop_assert_eq!(RsqlParser::default().parse_to_node(&code).ok(), Some(expr), "{}", code);
+    }
+
+    #[test]
+    fn test_formatted_roundtrip(expr in rsql_tree(), formatter in formatter()) {
+        let code = formatter.format(&expr)?;
+        prop_assert_eq!(RsqlParser::default().parse_to_node(&code).ok(), Some(expr), "{}", code);
+    }
+
+    #[test]
+    fn test_fiql_roundtrip(expr in tree(prop_oneof![
+        constraint("\\PC{1,4}", "\\PC{1,5}".prop_map(Value::Unquoted).boxed()),
+        "[a-z]{1,4}".prop_map(Expr::Exists),
+    ].boxed(), false)) {
+        let mut parser = FiqlParser::default();
+        parser.register_comparison(&Comparison::IN()).unwrap();
+        let code = expr.to_fiql_string()?;
+        prop_assert_eq!(parser.parse_to_node(&code).ok(), Some(expr), "{}", code);
+    }
+}
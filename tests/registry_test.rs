@@ -0,0 +1,127 @@
+use rsql::borrowed;
+use rsql::error::ParserError;
+use rsql::parser::fiql::FiqlParser;
+use rsql::parser::registry::ComparisonRegistry;
+use rsql::parser::rsql::RsqlParser;
+use rsql::parser::Parser;
+use rsql::{Arity, Comparison, Expr};
+use std::sync::Arc;
+use std::thread;
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn test_shared_registry() -> anyhow::Result<()> {
+    assert_send_sync::<ComparisonRegistry>();
+    assert_send_sync::<RsqlParser>();
+    assert_send_sync::<FiqlParser>();
+
+    let between = Comparison::new(&["=between="], Arity::Exact(2))?;
+    let registry = ComparisonRegistry::new().with(&[between.clone(), Comparison::IN()])?.freeze();
+    let rsql = Arc::new(RsqlParser::default().registry(Arc::clone(&registry))?);
+    let fiql = FiqlParser::default().registry(Arc::clone(&registry))?;
+    assert!(Arc::ptr_eq(&rsql.snapshot(), &fiql.snapshot()));
+
+    let expected = Expr::item("year", between.clone(), &["1", "10"])?;
+    let handles = (0..4).map(|_| {
+        let rsql = Arc::clone(&rsql);
+        thread::spawn(move || rsql.parse_to_node("year=between=(1,10)").map_err(|e| e.to_string()))
+    });
+    for handle in handles.collect::<Vec<_>>() {
+        assert_eq!(handle.join().unwrap(), Ok(expected.clone()));
+    }
+    assert_eq!(fiql.parse_to_node("year=between=(1,10)")?, expected);
+
+    // Registering copies the shared registry, the other parsers keep theirs
+    let mut fiql = fiql;
+    let like = Comparison::new(&["=like="], Arity::Exact(1))?;
+    fiql.register_comparison(&like)?;
+    assert!(!Arc::ptr_eq(&fiql.snapshot(), &registry));
+    assert!(rsql.get_comparison("=like=").is_none());
+    assert!(rsql.parse_to_node("a=like=b").is_err());
+    assert_eq!(fiql.snapshot().len(), registry.len() + 1);
+
+    // A reconfigured registry is built from a snapshot and swapped in
+    let mut next = (*rsql.snapshot()).clone();
+    next.remove_by_symbol("=between=");
+    next.register(&like)?;
+    let rsql = RsqlParser::default().registry(next.freeze())?;
+    assert!(rsql.parse_to_node("a=like=b").is_ok());
+    assert!(rsql.parse_to_node("year=between=(1,10)").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_registry_of_both_dialects() -> anyhow::Result<()> {
+    let registry = ComparisonRegistry::new()
+        .with(&[
+            Comparison::GREATER_THAN(),
+            Comparison::GREATER_THAN_OR_EQUAL(),
+            Comparison::LESS_THAN(),
+            Comparison::LESS_THAN_OR_EQUAL(),
+            Comparison::IN(),
+        ])?
+        .freeze();
+    let rsql = RsqlParser::default().registry(Arc::clone(&registry))?;
+    let fiql = FiqlParser::default().registry(Arc::clone(&registry))?;
+
+    let expected = Expr::And(vec![
+        Expr::item("a", Comparison::GREATER_THAN(), &["1"])?,
+        Expr::item("b", Comparison::LESS_THAN_OR_EQUAL(), &["5"])?,
+        Expr::item("c", Comparison::IN(), &["x", "y"])?,
+    ]);
+    assert_eq!(rsql.parse_to_node("a>1;b<=5;c=in=(x,y)")?, expected);
+    assert_eq!(rsql.parse_to_node("a=gt=1;b=le=5;c=in=(x,y)")?, expected);
+    assert_eq!(fiql.parse_to_node("a=gt=1;b=le=5;c=in=(x,y)")?, expected);
+    // The short symbols are not FIQL, although the registry holds them
+    assert!(fiql.parse_to_node("a>1").is_err());
+    assert!(FiqlParser::default().parse_to_node("a<=5").is_err());
+    assert!(FiqlParser::default().parse_to_node("a>=5").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_checked_registry() -> anyhow::Result<()> {
+    // FIQL has no `<`, a comparison needs a symbol the dialect reads
+    let less = Comparison::new(&["<"], Arity::Exact(1))?;
+    let registry = ComparisonRegistry::new().with(&[less])?.freeze();
+    assert!(matches!(
+        FiqlParser::default().registry(registry),
+        Err(ParserError::AmbiguousComparison(ref symbol)) if symbol == "<"
+    ));
+
+    let registry =
+        ComparisonRegistry::new().with(&[Comparison::new(&["=in="], Arity::Exact(1))?])?.freeze();
+    assert!(matches!(
+        RsqlParser::default().registry(registry),
+        Err(ParserError::ConflictingComparison(ref symbol)) if symbol == "=in="
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_shared_comparisons() -> anyhow::Result<()> {
+    let between = Comparison::new(&["=between="], Arity::Exact(2))?;
+    let registry = ComparisonRegistry::new().with(&[between])?.freeze();
+    let parser = RsqlParser::default().registry(Arc::clone(&registry))?;
+
+    // Parsed constraints share the comparisons of the registry and the built-in ones
+    let expr = parser.parse_borrowed("a=between=(1,2);b=between=(3,4);c==1;d==2")?;
+    let comparisons = match &expr {
+        borrowed::Expr::And(children) => children
+            .iter()
+            .map(|child| match child {
+                borrowed::Expr::Item(constraint) => Arc::clone(&constraint.comparison),
+                _ => panic!("constraint expected"),
+            })
+            .collect::<Vec<_>>(),
+        _ => panic!("AND expected"),
+    };
+    let registered = registry.get("=between=").expect("registered");
+    assert!(Arc::ptr_eq(&comparisons[0], registered));
+    assert!(Arc::ptr_eq(&comparisons[1], registered));
+    assert!(Arc::ptr_eq(&comparisons[2], &comparisons[3]));
+    assert!(Arc::ptr_eq(&comparisons[2], &parser.get_comparison("==").expect("built-in")));
+    assert_eq!(*comparisons[2], Comparison::EQUAL());
+    Ok(())
+}
@@ -1,24 +1,35 @@
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample;
+use rsql::borrowed;
+use rsql::error::{Expected, ParserError};
+use rsql::parser::options::ParserOptions;
 use rsql::parser::rsql::*;
 use rsql::parser::Parser;
-use rsql::Comparison;
 use rsql::Expr;
+use rsql::Formatter;
 use rsql::Operator;
+use rsql::{Arity, Comparison, Value};
+use std::borrow::Cow;
+
+fn strings(args: &[&str]) -> Vec<Value> {
+    args.iter().map(|arg| Value::String(arg.to_string())).collect()
+}
 
 #[test]
 fn test_simple() -> anyhow::Result<()> {
     let parser = RsqlParser::default();
 
-    let node = Expr::Node(
-        Operator::And,
-        Expr::boxed_item("name", Comparison::EQUAL(), &["Kill Bill"])?,
-        Expr::boxed_item("year", Comparison::GREATER_THAN(), &["2003"])?,
-    );
+    let node = Expr::And(vec![
+        Expr::item("name", Comparison::EQUAL(), &strings(&["Kill Bill"]))?,
+        Expr::item("year", Comparison::GREATER_THAN(), &["2003"])?,
+    ]);
 
     let code = r#"name=="Kill Bill";year=gt=2003"#;
-    assert_eq!(parser.parse_to_node(&code)?, node);
+    assert_eq!(parser.parse_to_node(code)?, node);
 
     let code = r#"name=="Kill Bill" and year>2003"#;
-    assert_eq!(parser.parse_to_node(&code)?, node);
+    assert_eq!(parser.parse_to_node(code)?, node);
 
     assert_eq!(node.to_string(), r#"name=='Kill Bill';year=gt=2003"#);
     assert_eq!(parser.parse_to_node(&node.to_string())?, node);
@@ -29,30 +40,23 @@ fn test_simple() -> anyhow::Result<()> {
 #[test]
 fn test_array() -> anyhow::Result<()> {
     let parser = RsqlParser::default();
-    let node = Expr::Node(
-        Operator::Or,
-        Expr::boxed_item("director", Comparison::EQUAL(), &["Christopher Nolan"])?,
-        Expr::boxed_item("actor", Comparison::EQUAL(), &["*Bale"])?,
-    );
+    let node = Expr::Or(vec![
+        Expr::item("director", Comparison::EQUAL(), &strings(&["Christopher Nolan"]))?,
+        Expr::item("actor", Comparison::EQUAL(), &["*Bale"])?,
+    ]);
 
-    let node = Expr::Node(
-        Operator::And,
-        Expr::boxed_item("genres", Comparison::IN(), &["sci-fi", "action"])?,
-        Box::new(node),
-    );
-
-    let node = Expr::Node(
-        Operator::And,
-        Box::new(node),
-        Expr::boxed_item("year", Comparison::GREATER_THAN_OR_EQUAL(), &["2000"])?,
-    );
+    let node = Expr::And(vec![
+        Expr::item("genres", Comparison::IN(), &["sci-fi", "action"])?,
+        node,
+        Expr::item("year", Comparison::GREATER_THAN_OR_EQUAL(), &["2000"])?,
+    ]);
 
     let code = r#"genres=in=(sci-fi,action) and (director=='Christopher Nolan' or actor==*Bale) and year>=2000"#;
-    assert_eq!(parser.parse_to_node(&code)?, node);
+    assert_eq!(parser.parse_to_node(code)?, node);
 
     let code =
         r#"genres=in=(sci-fi,action);(director=='Christopher Nolan',actor==*Bale);year=ge=2000"#;
-    assert_eq!(parser.parse_to_node(&code)?, node);
+    assert_eq!(parser.parse_to_node(code)?, node);
 
     assert_eq!(node.to_string(), code);
     assert_eq!(parser.parse_to_node(&node.to_string())?, node);
@@ -63,21 +67,16 @@ fn test_array() -> anyhow::Result<()> {
 #[test]
 fn test_sub_fields() -> anyhow::Result<()> {
     let parser = RsqlParser::default();
-    let node = Expr::Node(
-        Operator::And,
-        Expr::boxed_item("director.lastName", Comparison::EQUAL(), &["Nolan"])?,
-        Expr::boxed_item("year", Comparison::GREATER_THAN_OR_EQUAL(), &["2000"])?,
-    );
-    let node = Expr::Node(
-        Operator::And,
-        Box::new(node),
-        Expr::boxed_item("year", Comparison::LESS_THAN(), &["2010"])?,
-    );
+    let node = Expr::And(vec![
+        Expr::item("director.lastName", Comparison::EQUAL(), &["Nolan"])?,
+        Expr::item("year", Comparison::GREATER_THAN_OR_EQUAL(), &["2000"])?,
+        Expr::item("year", Comparison::LESS_THAN(), &["2010"])?,
+    ]);
 
     let code = r#"director.lastName==Nolan;year=ge=2000;year=lt=2010"#;
-    assert_eq!(parser.parse_to_node(&code)?, node);
+    assert_eq!(parser.parse_to_node(code)?, node);
     let code = r#"director.lastName==Nolan and year>=2000 and year<2010"#;
-    assert_eq!(parser.parse_to_node(&code)?, node);
+    assert_eq!(parser.parse_to_node(code)?, node);
 
     assert_eq!(node.to_string(), r#"director.lastName==Nolan;year=ge=2000;year=lt=2010"#);
     assert_eq!(parser.parse_to_node(&node.to_string())?, node);
@@ -88,25 +87,414 @@ fn test_sub_fields() -> anyhow::Result<()> {
 #[test]
 fn test_double_array() -> anyhow::Result<()> {
     let parser = RsqlParser::default();
-    let node = Expr::Node(
-        Operator::And,
-        Expr::boxed_item("genres", Comparison::IN(), &["sci-fi", "action"])?,
-        Expr::boxed_item("genres", Comparison::OUT(), &["romance", "animated", "horror"])?,
-    );
-    let node = Expr::Node(
-        Operator::Or,
-        Box::new(node),
-        Expr::boxed_item("director", Comparison::EQUAL(), &["Que*Tarantino"])?,
-    );
+    let node = Expr::Or(vec![
+        Expr::And(vec![
+            Expr::item("genres", Comparison::IN(), &["sci-fi", "action"])?,
+            Expr::item("genres", Comparison::OUT(), &["romance", "animated", "horror"])?,
+        ]),
+        Expr::item("director", Comparison::EQUAL(), &["Que*Tarantino"])?,
+    ]);
 
     let code = r#"genres=in=(sci-fi,action) and genres=out=(romance,animated,horror) or director==Que*Tarantino"#;
-    assert_eq!(parser.parse_to_node(&code)?, node);
+    assert_eq!(parser.parse_to_node(code)?, node);
 
     let code =
         r#"genres=in=(sci-fi,action);genres=out=(romance,animated,horror),director==Que*Tarantino"#;
-    assert_eq!(parser.parse_to_node(&code)?, node);
+    assert_eq!(parser.parse_to_node(code)?, node);
     assert_eq!(node.to_string(), code);
     assert_eq!(parser.parse_to_node(&node.to_string())?, node);
 
     Ok(())
 }
+
+#[test]
+fn test_spans() -> anyhow::Result<()> {
+    let parser = RsqlParser::default();
+    let code = "name==\"Kill\nBill\" and (year=in=(2003, 2004),title!=x)";
+    let (node, source_map) = parser.parse_with_spans(code)?;
+    assert_eq!(node, parser.parse_to_node(code)?);
+    assert_eq!(node.constraints().len(), source_map.constraints.len());
+
+    let name = &source_map.constraints[0];
+    assert_eq!(name.span.as_str(code), "name==\"Kill\nBill\"");
+    assert_eq!(name.selector.as_str(code), "name");
+    assert_eq!(name.comparison.as_str(code), "==");
+    assert_eq!(name.values[0].as_str(code), "\"Kill\nBill\"");
+
+    let year = &source_map.constraints[1];
+    assert_eq!(year.arguments.as_str(code), "(2003, 2004)");
+    assert_eq!(year.values.iter().map(|v| v.as_str(code)).collect::<Vec<_>>(), ["2003", "2004"]);
+    assert_eq!((year.selector.start.line, year.selector.start.column), (2, 12));
+    assert_eq!((year.values[1].start.line, year.values[1].start.column), (2, 27));
+
+    assert_eq!(source_map.constraints[2].comparison.as_str(code), "!=");
+    assert_eq!(
+        source_map
+            .operators
+            .iter()
+            .map(|op| (op.operator.clone(), op.span.as_str(code)))
+            .collect::<Vec<_>>(),
+        [(Operator::And, "and"), (Operator::Or, ",")]
+    );
+    assert_eq!(source_map.groups.len(), 1);
+    assert_eq!(source_map.groups[0].as_str(code), "(year=in=(2003, 2004),title!=x)");
+
+    Ok(())
+}
+
+#[test]
+fn test_syntax_error() -> anyhow::Result<()> {
+    let parser = RsqlParser::default();
+
+    let err = match parser.parse_to_node("name==foo;(year=gt=2003") {
+        Err(ParserError::Syntax(err)) => err,
+        res => panic!("unexpected result: {:?}", res),
+    };
+    assert_eq!(err.position.offset, 23);
+    assert_eq!((err.position.line, err.position.column), (1, 24));
+    assert_eq!(err.snippet, "");
+    assert_eq!(err.expected, [Expected::LogicalOperator, Expected::ClosingParenthesis]);
+    assert_eq!(
+        err.to_string(),
+        "Syntax error at 1:24, expected logical operator or closing parenthesis, found end of input"
+    );
+
+    let err = match parser.parse_to_node("name==foo)") {
+        Err(ParserError::Syntax(err)) => err,
+        res => panic!("unexpected result: {:?}", res),
+    };
+    assert_eq!(err.position.offset, 9);
+    assert_eq!(err.snippet, ")");
+    assert_eq!(err.expected, [Expected::LogicalOperator, Expected::EndOfInput]);
+
+    let err = match parser.parse_to_node("name==foo;\nyear 2003") {
+        Err(ParserError::Syntax(err)) => err,
+        res => panic!("unexpected result: {:?}", res),
+    };
+    assert_eq!((err.position.line, err.position.column), (2, 6));
+    assert_eq!(err.snippet, "2003");
+    assert_eq!(err.expected, [Expected::ComparisonOperator]);
+
+    let err = match parser.parse_to_node("genres=in=(sci-fi,)") {
+        Err(ParserError::Syntax(err)) => err,
+        res => panic!("unexpected result: {:?}", res),
+    };
+    assert_eq!(err.snippet, ")");
+    assert_eq!(err.expected, [Expected::Argument]);
+
+    Ok(())
+}
+
+#[test]
+fn test_recovering() -> anyhow::Result<()> {
+    let parser = RsqlParser::default();
+
+    let code = "name=like=foo;year==;(genres=in=(sci-fi,action),title==x";
+    let recovered = parser.parse_recovering(code);
+    assert_eq!(
+        recovered.expr,
+        Some(Expr::Or(vec![
+            Expr::item("genres", Comparison::IN(), &["sci-fi", "action"])?,
+            Expr::item("title", Comparison::EQUAL(), &["x"])?,
+        ]))
+    );
+
+    let diagnostics = &recovered.diagnostics;
+    assert_eq!(diagnostics.len(), 3);
+    assert_eq!(diagnostics[0].span.as_str(code), "name=like=foo");
+    assert!(
+        matches!(&diagnostics[0].error, ParserError::InvalidComparison(comp) if comp == "=like=")
+    );
+    assert_eq!(diagnostics[1].span.as_str(code), "year==");
+    assert!(matches!(
+        diagnostics[1].error,
+        ParserError::InvalidConstraintArgs { expected: Arity::Exact(1), count: 0 }
+    ));
+    assert_eq!(diagnostics[2].span.as_str(code), "(");
+    match &diagnostics[2].error {
+        ParserError::Syntax(err) => {
+            assert_eq!(err.position.offset, code.len());
+            assert_eq!(err.expected, [Expected::LogicalOperator, Expected::ClosingParenthesis]);
+        }
+        err => panic!("unexpected error: {:?}", err),
+    }
+
+    let code = "a==1 b==2),;c==3";
+    let recovered = parser.parse_recovering(code);
+    assert_eq!(recovered.expr.map(|expr| expr.to_string()), Some("a==1;b==2,c==3".to_string()));
+    let offsets = recovered
+        .diagnostics
+        .iter()
+        .map(|diagnostic| match &diagnostic.error {
+            ParserError::Syntax(err) => err.position.offset,
+            err => panic!("unexpected error: {:?}", err),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(offsets, [5, 9, 11]);
+
+    let recovered = parser.parse_recovering("name==\"Kill Bill\";year=gt=2003");
+    assert!(recovered.is_ok());
+    assert_eq!(recovered.expr, Some(parser.parse_to_node("name==\"Kill Bill\";year=gt=2003")?));
+
+    let recovered = parser.parse_recovering("");
+    assert!(recovered.expr.is_none());
+    assert_eq!(recovered.diagnostics.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_escaped() -> anyhow::Result<()> {
+    let parser = RsqlParser::default();
+
+    let node = Expr::boxed_item("name", Comparison::EQUAL(), &strings(&["a\"b"]))?;
+    assert_eq!(&parser.parse_to_node(r#"name=="a\"b""#)?, node.as_ref());
+    assert_eq!(&parser.parse_to_node(r#"name=='a"b'"#)?, node.as_ref());
+    assert_eq!(node.to_string(), r#"name=='a"b'"#);
+
+    let node = Expr::boxed_item(
+        "name",
+        Comparison::IN(),
+        &strings(&["it's", "\\", "tab\tnew\nline/", "\u{e9}\u{1F600}", "it's \"both\""]),
+    )?;
+    let code = r#"name=in=("it\'s",'\\','tab\tnew\nline\/',"\u00E9\ud83d\uDE00",'it\'s "both"')"#;
+    assert_eq!(&parser.parse_to_node(code)?, node.as_ref());
+    assert_eq!(&parser.parse_to_node(&node.to_string())?, node.as_ref());
+
+    let node = Expr::boxed_item("name", Comparison::EQUAL(), &strings(&["\u{FFFD}x"]))?;
+    assert_eq!(&parser.parse_to_node(r#"name=="\ud83dx""#)?, node.as_ref());
+
+    Ok(())
+}
+
+#[test]
+fn test_not() -> anyhow::Result<()> {
+    let parser = RsqlParser::default();
+    let a = Expr::item("a", Comparison::EQUAL(), &["1"])?;
+    let b = Expr::item("b", Comparison::EQUAL(), &["2"])?;
+
+    let node = Expr::Not(Box::new(Expr::Or(vec![a.clone(), b.clone()])));
+    assert_eq!(parser.parse_to_node("!(a==1,b==2)")?, node);
+    assert_eq!(parser.parse_to_node("not (a==1 or b==2)")?, node);
+    assert_eq!(node.to_string(), "!(a==1,b==2)");
+
+    let node = Expr::And(vec![Expr::Not(Box::new(a.clone())), b.clone()]);
+    assert_eq!(parser.parse_to_node("!a==1;b==2")?, node);
+    assert_eq!(parser.parse_to_node("not a==1 and b==2")?, node);
+    assert_eq!(node.to_string(), "!a==1;b==2");
+
+    let node = Expr::Not(Box::new(Expr::Not(Box::new(a.clone()))));
+    assert_eq!(parser.parse_to_node("!!a==1")?, node);
+    assert_eq!(parser.parse_to_node(&node.to_string())?, node);
+
+    // `not` is only a keyword when it is followed by a space or a group
+    let not = Expr::item("not", Comparison::EQUAL(), &["1"])?;
+    assert_eq!(parser.parse_to_node("not==1")?, not);
+    assert_eq!(parser.parse_to_node("not not==1")?, Expr::Not(Box::new(not)));
+
+    let (_, source_map) = parser.parse_with_spans("a==1;not (b==2)")?;
+    assert_eq!(source_map.negations.len(), 1);
+    assert_eq!(source_map.negations[0].range(), 5..8);
+
+    let recovered = parser.parse_recovering("!a==1;!(b==2,c==)");
+    assert_eq!(
+        recovered.expr,
+        Some(Expr::And(vec![Expr::Not(Box::new(a)), Expr::Not(Box::new(b))]))
+    );
+    assert_eq!(recovered.diagnostics.len(), 1);
+
+    let strict = RsqlParser::with_options(ParserOptions::default().negation(false));
+    match strict.parse_to_node("a==1;!b==2") {
+        Err(ParserError::Syntax(err)) => {
+            assert_eq!(err.position.offset, 5);
+            assert_eq!(err.expected, [Expected::Selector, Expected::OpeningParenthesis]);
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+    let recovered = strict.parse_recovering("!a==1;b==2");
+    assert_eq!(recovered.diagnostics.len(), 1);
+    assert_eq!(recovered.diagnostics[0].span.as_str("!a==1;b==2"), "!");
+
+    Ok(())
+}
+
+#[test]
+fn test_borrowed() -> anyhow::Result<()> {
+    let parser = RsqlParser::default();
+    let code = r#"name=="Kill Bill";title=in=(foo,'it\'s')"#;
+    let expr = parser.parse_borrowed(code)?;
+    match &expr {
+        borrowed::Expr::And(children) => match children.as_slice() {
+            [borrowed::Expr::Item(name), borrowed::Expr::Item(title)] => {
+                assert!(matches!(name.selector, Cow::Borrowed("name")));
+                assert!(matches!(
+                    name.arguments.0[..],
+                    [borrowed::Value::String(Cow::Borrowed("Kill Bill"))]
+                ));
+                assert!(matches!(
+                    title.arguments.0[..],
+                    [
+                        borrowed::Value::Unquoted(Cow::Borrowed("foo")),
+                        borrowed::Value::String(Cow::Owned(ref unescaped)),
+                    ] if unescaped == "it's"
+                ));
+            }
+            _ => panic!("unexpected children: {:?}", children),
+        },
+        _ => panic!("unexpected expr: {:?}", expr),
+    }
+    assert_eq!(expr.into_owned(), parser.parse_to_node(code)?);
+    Ok(())
+}
+
+#[test]
+fn test_arity() -> anyhow::Result<()> {
+    let parser = RsqlParser::default();
+    let expr = parser.parse_to_node("genres=in=(sci-fi);genres=out=action")?;
+    assert_eq!(expr.to_string(), "genres=in=(sci-fi);genres=out=(action)");
+    assert_eq!(parser.parse_to_node(&expr.to_string())?, expr);
+
+    assert!(matches!(
+        parser.parse_to_node("year==(1,2)"),
+        Err(ParserError::InvalidConstraintArgs { expected: Arity::Exact(1), count: 2 })
+    ));
+    let recovered = parser.parse_recovering("year==(1,2);a==1");
+    assert_eq!(recovered.diagnostics[0].span.as_str("year==(1,2);a==1"), "year==(1,2)");
+    assert_eq!(recovered.expr, Some(parser.parse_to_node("a==1")?));
+    Ok(())
+}
+
+#[test]
+fn test_custom_arity() -> anyhow::Result<()> {
+    let between = Comparison::new(&["=between="], Arity::Exact(2))?;
+    let is_null = Comparison::new(&["=isnull="], Arity::Range { min: 0, max: 1 })?;
+    let mut parser = RsqlParser::default();
+    parser.register_comparison(&between)?;
+    parser.register_comparison(&is_null)?;
+
+    let expr = parser.parse_to_node("year=between=(1,10);title=isnull=true")?;
+    assert_eq!(
+        expr,
+        Expr::And(vec![
+            Expr::item("year", between.clone(), &["1", "10"])?,
+            Expr::item("title", is_null.clone(), &["true"])?,
+        ])
+    );
+    assert_eq!(expr.to_string(), "year=between=(1,10);title=isnull=true");
+    assert!(matches!(
+        parser.parse_to_node("year=between=(1,5,10)"),
+        Err(ParserError::InvalidConstraintArgs { expected: Arity::Exact(2), count: 3 })
+    ));
+
+    let code = "title=isnull=;year=between=(1,10)";
+    let expr = parser.parse_to_node(code)?;
+    assert_eq!(
+        expr,
+        Expr::And(vec![
+            Expr::item("title", is_null.clone(), &[] as &[&str])?,
+            Expr::item("year", between.clone(), &["1", "10"])?,
+        ])
+    );
+    assert_eq!(expr.to_string(), "title=isnull=();year=between=(1,10)");
+    assert_eq!(parser.parse_to_node(&expr.to_string())?, expr);
+    let keywords = Formatter::rsql().keyword_operators(true).spaces(true);
+    assert_eq!(keywords.format(&expr)?, "title=isnull=() and year=between=(1,10)");
+    assert_eq!(parser.parse_to_node(&keywords.format(&expr)?)?, expr);
+    let expr = parser.parse_to_node("(title=isnull=),title=isnull=( ) or title=isnull=null")?;
+    assert_eq!(expr.to_string(), "title=isnull=(),title=isnull=(),title=isnull=null");
+    // Before a keyword operator the keyword is read as the argument
+    let expr = parser.parse_to_node("title=isnull=and or title==and")?;
+    assert_eq!(expr.to_string(), "title=isnull=and,title==and");
+    assert!(matches!(
+        parser.parse_to_node("title=="),
+        Err(ParserError::InvalidConstraintArgs { expected: Arity::Exact(1), count: 0 })
+    ));
+    assert_eq!(
+        Expr::item("year", between, &["1"]).map_err(|err| err.to_string()).unwrap_err(),
+        "Invalid Constraint arguments: expected exactly 2, found 1"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_comparison_id() -> anyhow::Result<()> {
+    let mut parser = RsqlParser::default();
+    parser.register_comparison(&Comparison::GREATER_THAN().alias("=after=")?)?;
+    for code in &["date=after=2003", "date=gt=2003", "date>2003"] {
+        match &parser.parse_to_node(code)? {
+            Expr::Item(constraint) => {
+                assert_eq!(constraint.comparison.get_id(), "greater_than");
+                assert_eq!(constraint.comparison, Comparison::GREATER_THAN());
+            }
+            expr => panic!("unexpected expr: {:?}", expr),
+        }
+    }
+
+    let json = serde_json::to_string(&Comparison::IN())?;
+    assert_eq!(
+        json,
+        r#"{"id":"in","symbols":["=in="],"arity":{"AtLeast":1},"description":"Equal to one of the arguments","category":"membership"}"#
+    );
+    let old = serde_json::from_str::<Comparison>(r#"{"symbols":["=like="],"multi_values":false}"#)?;
+    assert_eq!(old.get_id(), "=like=");
+    assert_eq!(old.arity(), Arity::Exact(1));
+    assert_eq!(old.get_description(), None);
+    let old = serde_json::from_str::<Comparison>(r#"{"symbols":["=in="],"multi_values":true}"#)?;
+    assert_eq!(old.arity(), Arity::AtLeast(1));
+    let any = serde_json::from_str::<Comparison>(r#"{"symbols":["=any="],"arity":"Any"}"#)?;
+    assert_eq!((any.get_id(), any.arity()), ("=any=", Arity::Any));
+
+    assert!(serde_json::from_str::<Comparison>(r#"{"symbols":[],"arity":"Any"}"#).is_err());
+    assert!(serde_json::from_str::<Comparison>(r#"{"symbols":["= ="],"arity":"Any"}"#).is_err());
+    assert!(serde_json::from_str::<Comparison>(r#"{"symbols":["=="]}"#).is_err());
+    Ok(())
+}
+
+/// Quoted strings, and unquoted values RSQL writes without quotes, among them keyword-like and
+/// non-finite tokens
+fn value() -> impl Strategy<Value = Value> {
+    let tokens = vec!["and", "OR", "not", "null", "true", "NaN", "inf", "-inf", "1e400", "2003"];
+    prop_oneof![
+        ".*".prop_map(Value::String),
+        "[^\"'();,=!~<> \t\r\n]+".prop_map(Value::Unquoted),
+        sample::select(tokens).prop_map(Value::from),
+    ]
+}
+
+fn typed_value() -> impl Strategy<Value = Value> {
+    prop_oneof![
+        ".*".prop_map(Value::String),
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::Bool),
+        any::<i64>().prop_map(Value::Integer),
+        any::<f64>().prop_filter("finite", |value| value.is_finite()).prop_map(Value::Float),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn test_lossless_arguments(
+        value in value(), values in vec(value(), 1..4), keywords in any::<bool>()
+    ) {
+        let expr = Expr::And(vec![
+            Expr::item("a", Comparison::EQUAL(), &[value]).unwrap(),
+            Expr::item("b", Comparison::IN(), &values).unwrap(),
+        ]);
+        let code = Formatter::rsql().keyword_operators(keywords).format(&expr)?;
+        prop_assert_eq!(RsqlParser::default().parse_to_node(&code).ok(), Some(expr), "{}", code);
+    }
+
+    #[test]
+    fn test_lossless_typed_arguments(values in vec(typed_value(), 1..4)) {
+        let expr = Expr::item("a", Comparison::IN(), &values).unwrap();
+        let parser = RsqlParser::with_options(ParserOptions::default().typed_values(true));
+        let code = expr.to_string();
+        prop_assert_eq!(parser.parse_to_node(&code).ok(), Some(expr), "{}", code);
+    }
+
+    #[test]
+    fn test_unwritable_arguments(text in "(.*[\"'();,=!~<> \t\r\n].*)?") {
+        // Quoted, the value would read back as a string
+        prop_assert!(Formatter::rsql().format(&Value::Unquoted(text)).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use rsql::parser::options::ParserOptions;
+use rsql::parser::rsql::RsqlParser;
+use rsql::parser::Parser;
+use rsql::{Comparison, Expr, Formatter};
+use serde::Deserialize;
+use std::thread;
+
+const SIZE: usize = 100_000;
+/// Far beyond what recursion fits in `small_stack`, lower than `SIZE` as every level holds
+/// two constraints
+const DEPTH: usize = 20_000;
+
+/// Runs `test` in a thread with a stack far too small for one frame per nesting level
+fn small_stack<F: FnOnce() -> anyhow::Result<()> + Send + 'static>(test: F) -> anyhow::Result<()> {
+    thread::Builder::new().stack_size(256 * 1024).spawn(test)?.join().expect("test panicked")
+}
+
+fn serde_round_trip(expr: &Expr) -> anyhow::Result<Expr> {
+    let json = serde_json::to_string(expr)?;
+    let mut deserializer = serde_json::Deserializer::from_str(&json);
+    deserializer.disable_recursion_limit();
+    Ok(Expr::deserialize(&mut deserializer)?)
+}
+
+#[test]
+fn test_long_chain() -> anyhow::Result<()> {
+    small_stack(|| {
+        let code = vec!["a==1"; SIZE].join(";");
+        let parser = RsqlParser::default();
+        let expr = parser.parse_to_node(&code)?;
+        assert_eq!(expr.constraints().len(), SIZE);
+        assert_eq!(expr.to_string(), code);
+        assert_eq!(serde_round_trip(&expr)?.to_string(), code);
+
+        let (_, source_map) = parser.parse_with_spans(&code)?;
+        assert_eq!(source_map.operators.len(), SIZE - 1);
+        assert!(parser.parse_recovering(&code).is_ok());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_deep_groups() -> anyhow::Result<()> {
+    small_stack(|| {
+        // Alternating OR and AND nodes, two levels per repetition
+        let code = format!("{}c==3{}", "a==1;(b==2,".repeat(DEPTH), ")".repeat(DEPTH));
+        let parser = RsqlParser::with_options(ParserOptions::default().max_depth(usize::MAX));
+        let expr = parser.parse_to_node(&code)?;
+        assert_eq!(expr.to_string(), code);
+        assert_eq!(serde_round_trip(&expr)?.to_string(), code);
+        assert_eq!(parser.parse_borrowed(&code)?.into_owned().to_string(), code);
+
+        // Compared, cloned and debugged without a frame per level
+        let clone = expr.clone();
+        assert!(clone == expr);
+        assert!(parser.parse_to_node(&code.replacen("c==3", "c==4", 1))? != expr);
+        assert!(format!("{:?}", clone).starts_with("And([Item(Constraint {"));
+        let borrowed = parser.parse_borrowed(&code)?;
+        assert!(borrowed.clone() == borrowed);
+        assert!(format!("{:?}", borrowed).ends_with("])])"));
+
+        // Without indentation, which would grow quadratically with the depth
+        let pretty = Formatter::rsql().max_width(8).indent(0).format(&expr)?;
+        assert_eq!(parser.parse_to_node(&pretty)?.to_string(), code);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_deep_negations() -> anyhow::Result<()> {
+    small_stack(|| {
+        let code = format!("{}a==1", "!".repeat(SIZE));
+        let parser = RsqlParser::with_options(ParserOptions::default().max_depth(SIZE));
+        let expr = parser.parse_to_node(&code)?;
+        assert_eq!(expr.to_string(), code);
+        assert_eq!(serde_round_trip(&expr)?.to_string(), code);
+        assert!(expr.clone() == expr);
+
+        let deep = (0..1_000_000)
+            .try_fold(Expr::item("a", Comparison::EQUAL(), &["1"])?, |expr, _| {
+                Ok::<_, anyhow::Error>(Expr::Not(Box::new(expr)))
+            })?;
+        drop(deep);
+        Ok(())
+    })
+}